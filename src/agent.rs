@@ -1,5 +1,52 @@
-use std::fmt::Debug;
+use std::{any::Any, fmt::Debug, task::Waker};
 
-pub trait Agent: Debug {
-    fn step(&self) -> bool;
+use anyhow::anyhow;
+
+/// outcome of a single [`Agent::step`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// consumed or produced elements.
+    Progressed,
+    /// nothing to do until an input receives an element.
+    Idle,
+    /// retired: the agent will never do anything again.
+    Finished,
+}
+
+pub trait Agent: Debug + Send {
+    fn step(&self) -> anyhow::Result<Step>;
+    /// the input pipes of the agent, in operand order.
+    fn inputs(&self) -> Vec<InputState>;
+    /// registers `waker` to every input pipe, and to every bounded output pipe
+    /// for when it drains, so that the agent is woken when it may be able to progress again.
+    fn watch(&self, waker: &Waker);
+    /// the output pipes too full to fire into.
+    fn full_outputs(&self) -> Vec<String> {
+        Vec::new()
+    }
+    /// whether `step` may block on I/O. executors that can should run such steps
+    /// aside from the other agents.
+    fn is_blocking(&self) -> bool {
+        false
+    }
+}
+
+/// the error of a step that panicked with `payload`, for executors stepping agents
+/// on threads whose panics would otherwise go unnoticed.
+pub fn panicked(payload: Box<dyn Any + Send>) -> anyhow::Error {
+    let message = match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "Box<dyn Any>".to_string(),
+        },
+    };
+    anyhow!("panicked: {}", message)
+}
+
+/// snapshot of an input pipe, used to explain why an agent is blocked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputState {
+    pub pipe: String,
+    pub pending: usize,
 }
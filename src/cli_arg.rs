@@ -1,6 +1,11 @@
-use std::cell::RefCell;
+use std::{cell::RefCell, task::Waker};
 
-use crate::{agent::Agent, deduct::AgentPrecursor, pipe::PipeSender};
+use crate::{
+    agent::{Agent, InputState, Step},
+    deduct::AgentPrecursor,
+    pipe::PipeSender,
+    span::Origin,
+};
 
 #[derive(Debug)]
 pub struct CliArgAgent {
@@ -8,21 +13,44 @@ pub struct CliArgAgent {
     argv: Vec<String>,
     pc: PipeSender<usize>,
     pv: PipeSender<String>,
+    /// elements sent so far: `argc` first, then each of `argv`.
     loc: RefCell<usize>,
 }
 
 impl Agent for CliArgAgent {
-    fn step(&self) -> bool {
+    fn step(&self) -> anyhow::Result<Step> {
         let loc = *self.loc.borrow();
         if loc == 0 {
+            if !self.pc.has_room() {
+                return Ok(Step::Idle);
+            }
             self.pc.send(self.argc);
+        } else if loc <= self.argc {
+            if !self.pv.has_room() {
+                return Ok(Step::Idle);
+            }
+            self.pv.send(self.argv[loc - 1].clone());
+        } else {
+            return Ok(Step::Finished);
         }
-        if loc < self.argc {
-            self.pv.send(self.argv[loc].clone());
-            *self.loc.borrow_mut() += 1;
-            true
+        *self.loc.borrow_mut() += 1;
+        Ok(Step::Progressed)
+    }
+    fn inputs(&self) -> Vec<InputState> {
+        Vec::new()
+    }
+    fn watch(&self, waker: &Waker) {
+        self.pc.watch_room(waker);
+        self.pv.watch_room(waker);
+    }
+    fn full_outputs(&self) -> Vec<String> {
+        let loc = *self.loc.borrow();
+        if loc == 0 && !self.pc.has_room() {
+            vec!["#".to_string()]
+        } else if loc > 0 && loc <= self.argc && !self.pv.has_room() {
+            vec!["@".to_string()]
         } else {
-            false
+            Vec::new()
         }
     }
 }
@@ -38,15 +66,23 @@ impl CliArgAgentPrecursor {
 }
 
 impl AgentPrecursor<String> for CliArgAgentPrecursor {
-    fn deduct(&self, idx: &mut crate::deduct::PipeTypeIndex<String>) -> anyhow::Result<()> {
-        idx.require::<usize, _>("#")?;
-        idx.require::<String, _>("@")?;
+    fn pipes(&self) -> Vec<String> {
+        vec!["#".to_string(), "@".to_string()]
+    }
+    fn deduct(
+        &self,
+        idx: &mut crate::deduct::PipeTypeIndex<String>,
+        origin: &Origin,
+    ) -> anyhow::Result<()> {
+        idx.require::<usize, _>("#", origin)?;
+        idx.require::<String, _>("@", origin)?;
         Ok(())
     }
 
     fn build(
         self: Box<Self>,
         idx: &crate::deduct::PipeIndex<String>,
+        _origin: &Origin,
     ) -> anyhow::Result<Box<dyn Agent>> {
         let pc = idx.require_sender("#")?;
         let pv = idx.require_sender("@")?;
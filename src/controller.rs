@@ -1,68 +1,871 @@
 use crate::{
-    agent::Agent,
-    deduct::{AgentPrecursor, PipeTypeIndex},
+    agent::{Agent, InputState, Step},
+    deduct::{AgentPrecursor, Capacity, PipeIndex, PipeTypeIndex, UntypedPipes},
+    diagnostic::Diagnostics,
+    pipe::Usage,
+    schedule::{ReadyQueue, Rng},
+    span::{Origin, Span},
 };
-use std::hash::Hash;
+use std::{collections::HashMap, error::Error, fmt::Display, hash::Hash, sync::Arc, task::Waker};
 
 #[derive(Debug)]
 pub struct Builder<K> {
     idx: PipeTypeIndex<K>,
-    precursors: Vec<Box<dyn AgentPrecursor<K>>>,
+    precursors: Vec<(Origin, Box<dyn AgentPrecursor<K>>)>,
+    capacity: Capacity<K>,
+    usage: Arc<Usage>,
 }
 impl<K> Builder<K> {
     pub fn new() -> Self {
         Self {
             idx: PipeTypeIndex::new(),
             precursors: Vec::new(),
+            capacity: Capacity::unbounded(),
+            usage: Arc::default(),
         }
     }
 
-    pub fn put(&mut self, precursor: Box<dyn AgentPrecursor<K>>) {
-        self.precursors.push(precursor);
+    /// the element counts of every pipe built by [`Builder::build`].
+    pub fn usage(&self) -> Arc<Usage> {
+        self.usage.clone()
     }
 
-    pub fn deduct_once(&mut self) -> anyhow::Result<bool> {
+    /// bounds the pipes built by [`Builder::build`].
+    pub fn set_capacity(&mut self, capacity: Capacity<K>) {
+        self.capacity = capacity;
+    }
+
+    /// registers a precursor. `origin` is where it came from: errors are located
+    /// there, and runtime reports name it.
+    pub fn put(&mut self, origin: Origin, precursor: Box<dyn AgentPrecursor<K>>) {
+        self.precursors.push((origin, precursor));
+    }
+
+    /// a precursor that fails to deduce is reported to `diagnostics` and replaced by [`Inert`],
+    /// and the types it fixed before failing are forgotten.
+    pub fn deduct_once(&mut self, diagnostics: &mut Diagnostics) -> anyhow::Result<bool>
+    where
+        K: Hash + Eq,
+    {
         let count_pre = self.idx.concrete_count();
-        for precursor in self.precursors.iter_mut() {
-            let precursor = precursor.as_mut();
-            precursor.deduct(&mut self.idx)?;
+        for (origin, precursor) in self.precursors.iter_mut() {
+            let checkpoint = self.idx.checkpoint();
+            if let Err(e) = precursor.deduct(&mut self.idx, origin) {
+                self.idx.rollback(checkpoint);
+                diagnostics.invalid(origin.locate(e))?;
+                *precursor = Box::new(Inert);
+            }
         }
         let count_post = self.idx.concrete_count();
         Ok(count_post > count_pre)
     }
 
-    pub fn deduct(&mut self) -> anyhow::Result<()> {
-        while self.deduct_once()? {}
-        Ok(())
+    /// deduces pipe types until a fixpoint, then checks that every pipe got one.
+    pub fn deduct(&mut self, diagnostics: &mut Diagnostics) -> anyhow::Result<()>
+    where
+        K: Clone + Eq + Hash + Display,
+    {
+        while self.deduct_once(diagnostics)? {}
+        self.validate(diagnostics)
     }
 
-    pub fn build(self) -> anyhow::Result<Executor>
+    /// reports [`UntypedPipes`] to `diagnostics` if some pipe was never typed, and replaces
+    /// the precursors using such pipes by [`Inert`].
+    fn validate(&mut self, diagnostics: &mut Diagnostics) -> anyhow::Result<()>
+    where
+        K: Clone + Eq + Hash + Display,
+    {
+        let mut untyped: Vec<(K, Vec<Origin>)> = Vec::new();
+        for (origin, precursor) in self.precursors.iter() {
+            for pipe in precursor.pipes() {
+                if self.idx.is_typed(&pipe) {
+                    continue;
+                }
+                match untyped.iter_mut().find(|(k, _)| *k == pipe) {
+                    Some((_, origins)) => {
+                        if !origins.contains(origin) {
+                            origins.push(origin.clone())
+                        }
+                    }
+                    None => untyped.push((pipe, vec![origin.clone()])),
+                }
+            }
+        }
+        if untyped.is_empty() {
+            return Ok(());
+        }
+        for (origin, precursor) in self.precursors.iter_mut() {
+            if untyped.iter().any(|(_, origins)| origins.contains(origin)) {
+                *precursor = Box::new(Inert);
+            }
+        }
+        diagnostics.invalid(
+            UntypedPipes {
+                pipes: untyped
+                    .into_iter()
+                    .map(|(k, origins)| (k.to_string(), origins))
+                    .collect(),
+            }
+            .into(),
+        )
+    }
+
+    /// builds every agent, in the order the precursors were put. a precursor that fails
+    /// to build is reported to `diagnostics` and replaced by [`Inert`].
+    pub fn build(self, diagnostics: &mut Diagnostics) -> anyhow::Result<Vec<Slot>>
     where
         K: Eq + Hash,
     {
-        let idx = self.idx.generate();
+        let idx = self.idx.generate(&self.capacity, &self.usage);
 
-        let agents: anyhow::Result<Vec<Box<dyn Agent>>> = self
-            .precursors
+        self.precursors
             .into_iter()
-            .map(|p| {
-                let a = p.build(&idx);
-                a
+            .map(|(origin, p)| {
+                let agent = match p.build(&idx, &origin) {
+                    Ok(agent) => agent,
+                    Err(e) => {
+                        diagnostics.invalid(origin.locate(e))?;
+                        Box::new(Inert)
+                    }
+                };
+                let mut slot = Slot::new(origin.to_string(), agent);
+                slot.span = origin.span().cloned();
+                Ok(slot)
             })
-            .collect();
-        let agents = agents?;
+            .collect()
+    }
+}
 
-        Ok(Executor { agents })
+/// stands for a tuple that does not make a valid instruction: it uses no pipe,
+/// and retires on its first step.
+#[derive(Debug)]
+pub struct Inert;
+impl Agent for Inert {
+    fn step(&self) -> anyhow::Result<Step> {
+        Ok(Step::Finished)
+    }
+    fn inputs(&self) -> Vec<InputState> {
+        Vec::new()
     }
+    fn watch(&self, _waker: &Waker) {}
 }
+impl<K> AgentPrecursor<K> for Inert {
+    fn pipes(&self) -> Vec<K> {
+        Vec::new()
+    }
+    fn deduct(&self, _idx: &mut PipeTypeIndex<K>, _origin: &Origin) -> anyhow::Result<()> {
+        Ok(())
+    }
+    fn build(
+        self: Box<Self>,
+        _idx: &PipeIndex<K>,
+        _origin: &Origin,
+    ) -> anyhow::Result<Box<dyn Agent>> {
+        Ok(Box::new(Inert))
+    }
+}
+
+/// runs the agents built by [`Builder`].
+pub trait Execute {
+    /// runs until every agent retired. exceeding `max_steps` aborts with
+    /// [`StepLimitExceeded`], reaching quiescence with live agents aborts with [`Stalled`].
+    /// whatever the executor, a step is one call of [`Agent::step`].
+    fn run(&mut self, max_steps: Option<usize>) -> anyhow::Result<()>;
+    /// statistics so far, with the agents still alive listed after the retired ones.
+    fn stats(&self) -> Stats;
+}
+
+/// an agent together with where it came from and its accounting.
+#[derive(Debug)]
+pub struct Slot {
+    pub origin: String,
+    /// the instruction the agent was built from, if any.
+    pub span: Option<Span>,
+    pub agent: Box<dyn Agent>,
+    pub stats: AgentStats,
+}
+impl Slot {
+    pub fn new(origin: String, agent: Box<dyn Agent>) -> Self {
+        Self {
+            origin,
+            span: None,
+            agent,
+            stats: AgentStats::default(),
+        }
+    }
 
+    /// wraps an error returned by a step of the agent.
+    pub fn fail(&self, e: anyhow::Error) -> anyhow::Error {
+        let e = match &self.span {
+            Some(span) => e.context(span.clone()),
+            None => e,
+        };
+        e.context(format!("{} failed", self.origin))
+    }
+}
+
+/// event-driven executor: an agent is stepped only when it was woken by one of
+/// its input pipes, or when it progressed on its previous step.
 #[derive(Debug)]
 pub struct Executor {
-    agents: Vec<Box<dyn Agent>>,
+    /// indexed by agent id. retired agents leave `None` behind.
+    agents: Vec<Option<Slot>>,
+    alive: usize,
+    ready: Arc<ReadyQueue>,
+    /// set by [`Executor::randomized`].
+    rng: Option<Rng>,
+    /// set by [`Executor::limited`].
+    limits: Option<(Limits, Arc<Usage>)>,
+    /// set by [`Execute::run`].
+    max_steps: Option<usize>,
+    stats: Stats,
 }
+/// upper bound of the steps an agent may take in a single tick of a randomized schedule.
+const MAX_DRAIN: usize = 4;
+
 impl Executor {
-    pub fn step(&mut self) -> bool {
-        self.agents.retain(|agent| agent.step());
-        !self.agents.is_empty()
+    pub fn new(agents: Vec<Slot>) -> Self {
+        let ready = Arc::new(ReadyQueue::default());
+        let agents: Vec<Option<Slot>> = agents
+            .into_iter()
+            .enumerate()
+            .map(|(id, slot)| {
+                slot.agent.watch(&ready.waker(id));
+                ready.push(id);
+                Some(slot)
+            })
+            .collect();
+        Self {
+            alive: agents.len(),
+            agents,
+            ready,
+            rng: None,
+            limits: None,
+            max_steps: None,
+            stats: Stats::default(),
+        }
+    }
+
+    /// shuffles the runnable agents on every step, and lets each of them drain a
+    /// random number of elements (up to [`MAX_DRAIN`]) before the next agent runs.
+    /// the same seed always yields the same schedule.
+    pub fn randomized(mut self, seed: u64) -> Self {
+        self.rng = Some(Rng::new(seed));
+        self
+    }
+
+    /// aborts with [`LimitExceeded`] as soon as the pipes measured by `usage`,
+    /// or the agents, go beyond `limits`.
+    pub fn limited(mut self, limits: Limits, usage: Arc<Usage>) -> Self {
+        self.limits = Some((limits, usage));
+        self
+    }
+
+    fn check_limits(&self) -> anyhow::Result<()> {
+        let Some((limits, usage)) = &self.limits else {
+            return Ok(());
+        };
+        for (resource, limit, used) in [
+            (Resource::Buffered, limits.buffered, usage.buffered()),
+            (Resource::Produced, limits.produced, usage.produced()),
+            (Resource::Fired, limits.fired, self.stats.fired),
+        ] {
+            if let Some(limit) = limit.filter(|limit| used > *limit) {
+                return Err(
+                    LimitExceeded::new(self.stats.steps, resource, limit, self.live()).into(),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// steps every runnable agent once. returns `false` if no agent was runnable,
+    /// which means the pipeline is quiescent.
+    pub fn step(&mut self) -> anyhow::Result<bool> {
+        let mut ids = self.ready.take();
+        if ids.is_empty() {
+            return Ok(false);
+        }
+        if let Some(rng) = self.rng.as_mut() {
+            rng.shuffle(ids.make_contiguous());
+        }
+        for id in ids {
+            let drain = match self.rng.as_mut() {
+                Some(rng) => rng.below(MAX_DRAIN) + 1,
+                None => 1,
+            };
+            for n in 1..=drain {
+                if self.agents[id].is_none() {
+                    break;
+                }
+                if self
+                    .max_steps
+                    .is_some_and(|max_steps| self.stats.steps >= max_steps)
+                {
+                    return Err(StepLimitExceeded::new(self.stats.steps, self.live()).into());
+                }
+                let slot = self.agents[id].as_mut().unwrap();
+                let step = slot.agent.step();
+                self.stats.steps += 1;
+                match step {
+                    Ok(Step::Progressed) => {
+                        slot.stats.progressed();
+                        self.stats.fired += 1;
+                        // more input may be waiting
+                        if n == drain {
+                            self.ready.push(id);
+                        }
+                    }
+                    Ok(Step::Idle) => {
+                        slot.stats.idled();
+                        self.stats.idled += 1;
+                        break;
+                    }
+                    Ok(Step::Finished) => {
+                        let slot = self.agents[id].take().unwrap();
+                        self.stats.agents.push((slot.origin, slot.stats));
+                        self.alive -= 1;
+                    }
+                    Err(e) => return Err(slot.fail(e)),
+                }
+                self.check_limits()?;
+            }
+        }
+        Ok(true)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.alive == 0
+    }
+
+    fn live(&self) -> impl Iterator<Item = &Slot> {
+        self.agents.iter().flatten()
+    }
+}
+impl Execute for Executor {
+    fn run(&mut self, max_steps: Option<usize>) -> anyhow::Result<()> {
+        self.max_steps = max_steps;
+        while !self.is_finished() {
+            if !self.step()? {
+                return Err(Stalled::new(self.stats.steps, self.live()).into());
+            }
+        }
+        Ok(())
+    }
+
+    fn stats(&self) -> Stats {
+        let mut stats = self.stats.clone();
+        for slot in self.live() {
+            stats.agents.push((slot.origin.clone(), slot.stats.clone()));
+        }
+        stats
+    }
+}
+
+/// per-agent accounting of how often an agent fired and how long it had to wait.
+#[derive(Debug, Clone, Default)]
+pub struct AgentStats {
+    pub fired: usize,
+    pub idled: usize,
+    /// idle steps since the last firing.
+    pub waiting: usize,
+    /// the longest run of consecutive idle steps.
+    pub longest_wait: usize,
+}
+impl AgentStats {
+    pub fn progressed(&mut self) {
+        self.fired += 1;
+        self.waiting = 0;
+    }
+    pub fn idled(&mut self) {
+        self.idled += 1;
+        self.waiting += 1;
+        self.longest_wait = self.longest_wait.max(self.waiting);
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    /// the agent steps taken, as counted against `max_steps`.
+    pub steps: usize,
+    pub fired: usize,
+    pub idled: usize,
+    /// per-agent statistics, in the order the agents retired.
+    pub agents: Vec<(String, AgentStats)>,
+}
+impl Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} steps, {} firings, {} idle polls",
+            self.steps, self.fired, self.idled
+        )?;
+        for (origin, s) in self.agents.iter() {
+            write!(
+                f,
+                "\n    {}: fired {}, idle {}, longest wait {}",
+                origin, s.fired, s.idled, s.longest_wait
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct StepLimitExceeded {
+    pub steps: usize,
+    pub alive: Vec<String>,
+}
+impl StepLimitExceeded {
+    pub fn new<'a>(steps: usize, alive: impl Iterator<Item = &'a Slot>) -> Self {
+        Self {
+            steps,
+            alive: alive.map(|slot| slot.origin.clone()).collect(),
+        }
+    }
+}
+impl Display for StepLimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "step limit exceeded after {} steps, {} agents still alive:",
+            self.steps,
+            self.alive.len()
+        )?;
+        for origin in self.alive.iter() {
+            write!(f, "\n    {}", origin)?;
+        }
+        Ok(())
+    }
+}
+impl Error for StepLimitExceeded {}
+
+/// caps on what a pipeline may consume. `None` is unlimited.
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    /// elements held in memory at once, summed over every pipe.
+    pub buffered: Option<usize>,
+    /// elements ever sent.
+    pub produced: Option<usize>,
+    /// agent firings.
+    pub fired: Option<usize>,
+}
+impl Limits {
+    pub fn is_unlimited(&self) -> bool {
+        self.buffered.is_none() && self.produced.is_none() && self.fired.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resource {
+    Buffered,
+    Produced,
+    Fired,
+}
+impl Display for Resource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Resource::Buffered => "buffered elements",
+            Resource::Produced => "produced elements",
+            Resource::Fired => "firings",
+        })
+    }
+}
+
+/// how many pipes [`LimitExceeded`] lists.
+const TOP_PIPES: usize = 5;
+
+/// the pipeline went beyond one of its [`Limits`].
+#[derive(Debug)]
+pub struct LimitExceeded {
+    pub steps: usize,
+    pub resource: Resource,
+    pub limit: usize,
+    /// the pipes holding the most unread elements, most first.
+    pub pipes: Vec<InputState>,
+}
+impl LimitExceeded {
+    pub fn new<'a>(
+        steps: usize,
+        resource: Resource,
+        limit: usize,
+        alive: impl Iterator<Item = &'a Slot>,
+    ) -> Self {
+        // a pipe holds as many elements as its slowest reader has left to read
+        let mut held: HashMap<String, usize> = HashMap::new();
+        for input in alive.flat_map(|slot| slot.agent.inputs()) {
+            let n = held.entry(input.pipe).or_default();
+            *n = (*n).max(input.pending);
+        }
+        let mut pipes: Vec<InputState> = held
+            .into_iter()
+            .filter(|(_, pending)| *pending != 0)
+            .map(|(pipe, pending)| InputState { pipe, pending })
+            .collect();
+        pipes.sort_by(|a, b| b.pending.cmp(&a.pending).then_with(|| a.pipe.cmp(&b.pipe)));
+        pipes.truncate(TOP_PIPES);
+        Self {
+            steps,
+            resource,
+            limit,
+            pipes,
+        }
+    }
+}
+impl Display for LimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "limit of {} {} exceeded after {} steps",
+            self.limit, self.resource, self.steps
+        )?;
+        if !self.pipes.is_empty() {
+            write!(f, ", pipes holding the most elements:")?;
+        }
+        for pipe in self.pipes.iter() {
+            write!(f, "\n    {:?}: {}", pipe.pipe, pipe.pending)?;
+        }
+        Ok(())
+    }
+}
+impl Error for LimitExceeded {}
+
+/// the pipeline reached a fixpoint while some agents were still alive.
+#[derive(Debug)]
+pub struct Stalled {
+    pub steps: usize,
+    pub blocked: Vec<BlockedAgent>,
+}
+#[derive(Debug)]
+pub struct BlockedAgent {
+    pub origin: String,
+    /// inputs with nothing to read.
+    pub waiting: Vec<InputState>,
+    /// inputs holding unread elements.
+    pub others: Vec<InputState>,
+    /// outputs too full to fire into.
+    pub full: Vec<String>,
+}
+impl Stalled {
+    pub fn new<'a>(steps: usize, alive: impl Iterator<Item = &'a Slot>) -> Self {
+        let blocked = alive
+            .map(|slot| {
+                let (waiting, others) = slot
+                    .agent
+                    .inputs()
+                    .into_iter()
+                    .partition(|input| input.pending == 0);
+                BlockedAgent {
+                    origin: slot.origin.clone(),
+                    waiting,
+                    others,
+                    full: slot.agent.full_outputs(),
+                }
+            })
+            .collect();
+        Self { steps, blocked }
+    }
+}
+impl Display for Stalled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "pipeline stalled after {} steps, {} agents blocked:",
+            self.steps,
+            self.blocked.len()
+        )?;
+        for b in self.blocked.iter() {
+            write!(f, "\n    {}", b.origin)?;
+            let waiting: Vec<String> = b.waiting.iter().map(|i| format!("{:?}", i.pipe)).collect();
+            if !waiting.is_empty() {
+                write!(f, "\n        waiting on {}", waiting.join(", "))?;
+            }
+            for other in b.others.iter() {
+                write!(f, "\n        {} unread in {:?}", other.pending, other.pipe)?;
+            }
+            for pipe in b.full.iter() {
+                write!(f, "\n        {:?} is full", pipe)?;
+            }
+        }
+        Ok(())
+    }
+}
+impl Error for Stalled {}
+
+#[cfg(test)]
+mod tests {
+    use std::{any::TypeId, cell::RefCell, task::Waker};
+
+    use crate::{
+        agent::{Agent, InputState, Step},
+        cli_arg::CliArgAgentPrecursor,
+        diagnostic::{Diagnostics, Invalid},
+        operator::{
+            self,
+            arith::{self, Op, Overflow},
+            load::Load,
+            IOp, IPrecursor,
+        },
+        parser::{parse, Syntax},
+        span::Origin,
+    };
+
+    use super::{
+        Builder, Capacity, Execute, Executor, Inert, LimitExceeded, Limits, Resource, Slot,
+        Stalled, StepLimitExceeded, UntypedPipes,
+    };
+
+    #[derive(Debug)]
+    struct Countdown(RefCell<usize>);
+    impl Agent for Countdown {
+        fn step(&self) -> anyhow::Result<Step> {
+            let mut n = self.0.borrow_mut();
+            *n = n.saturating_sub(1);
+            Ok(if *n > 0 {
+                Step::Progressed
+            } else {
+                Step::Finished
+            })
+        }
+        fn inputs(&self) -> Vec<InputState> {
+            Vec::new()
+        }
+        fn watch(&self, _waker: &Waker) {}
+    }
+
+    #[derive(Debug)]
+    struct Starving;
+    impl Agent for Starving {
+        fn step(&self) -> anyhow::Result<Step> {
+            Ok(Step::Idle)
+        }
+        fn inputs(&self) -> Vec<InputState> {
+            vec![
+                InputState {
+                    pipe: "A".to_string(),
+                    pending: 2,
+                },
+                InputState {
+                    pipe: "B".to_string(),
+                    pending: 0,
+                },
+            ]
+        }
+        fn watch(&self, _waker: &Waker) {}
+    }
+
+    #[derive(Debug)]
+    struct Echo;
+    impl IOp for Echo {
+        type Input = String;
+        type Output = String;
+        fn new(_: String) -> anyhow::Result<Self> {
+            Ok(Echo)
+        }
+        fn exec(&self, e: &String) -> impl IntoIterator<Item = String> {
+            [e.clone()]
+        }
+    }
+
+    fn executor(n: usize) -> Executor {
+        Executor::new(vec![Slot::new(
+            "countdown".to_string(),
+            Box::new(Countdown(RefCell::new(n))),
+        )])
+    }
+
+    #[test]
+    fn run_until_retired() -> anyhow::Result<()> {
+        let mut e = executor(10);
+        e.run(None)?;
+        let stats = e.stats();
+        assert_eq!(stats.steps, 10);
+        assert_eq!(stats.fired, 9);
+        assert_eq!(stats.agents.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn run_step_limit() {
+        let mut e = executor(10);
+        let err = e.run(Some(3)).unwrap_err();
+        let err = err.downcast::<StepLimitExceeded>().unwrap();
+        assert_eq!(err.steps, 3);
+        assert_eq!(err.alive, vec!["countdown".to_string()]);
+    }
+
+    #[test]
+    fn run_limited() {
+        let limits = Limits {
+            fired: Some(3),
+            ..Limits::default()
+        };
+        let mut e = executor(10).limited(limits, Default::default());
+        let err = e.run(None).unwrap_err();
+        let err = err.downcast::<LimitExceeded>().unwrap();
+        assert_eq!(err.resource, Resource::Fired);
+        // the fourth step fires once too often
+        assert_eq!(err.steps, 4);
+    }
+
+    #[test]
+    fn run_randomized() -> anyhow::Result<()> {
+        let retired = |seed| -> anyhow::Result<Vec<String>> {
+            let agents = (1..=8)
+                .map(|n| Slot::new(n.to_string(), Box::new(Countdown(RefCell::new(n))) as _))
+                .collect();
+            let mut e = Executor::new(agents).randomized(seed);
+            e.run(None)?;
+            Ok(e.stats().agents.into_iter().map(|(o, _)| o).collect())
+        };
+        assert_eq!(retired(1)?, retired(1)?);
+        assert_eq!(retired(1)?.len(), 8);
+        Ok(())
+    }
+
+    #[test]
+    fn run_stalled() {
+        let mut e = Executor::new(vec![Slot::new("starving".to_string(), Box::new(Starving))]);
+        let err = e.run(None).unwrap_err();
+        let err = err.downcast::<Stalled>().unwrap();
+        assert_eq!(err.blocked.len(), 1);
+        assert_eq!(err.blocked[0].origin, "starving");
+        assert_eq!(err.blocked[0].waiting[0].pipe, "B");
+        assert_eq!(err.blocked[0].others[0].pipe, "A");
+        assert_eq!(err.blocked[0].others[0].pending, 2);
+        assert_eq!(e.stats().agents[0].1.longest_wait, 1);
+    }
+
+    #[test]
+    fn run_dead_input() -> anyhow::Result<()> {
+        // nothing ever sends into `B`: the argument left in `A` is reported, not dropped
+        let mut diagnostics = Diagnostics::new(Invalid::Error);
+        let mut b = Builder::new();
+        b.put(
+            Origin::Arguments,
+            Box::new(CliArgAgentPrecursor::new(Vec::new())),
+        );
+        for instruction in parse("#0~!!HSAAB+C", Syntax::Aligned, &mut diagnostics)? {
+            let precursor = operator::precursor(&instruction, Overflow::Wrapping)?;
+            b.put(Origin::Instruction(instruction.span), precursor);
+        }
+        b.deduct(&mut diagnostics)?;
+        let mut e = Executor::new(b.build(&mut diagnostics)?);
+        let err = e.run(None).unwrap_err();
+        let err = err.downcast::<Stalled>().unwrap();
+        assert_eq!(err.blocked.len(), 1);
+        assert_eq!(err.blocked[0].origin, "instruction 2 \"AB+C\"");
+        assert_eq!(err.blocked[0].waiting[0].pipe, "B");
+        assert_eq!(err.blocked[0].others[0].pipe, "A");
+        assert_eq!(err.blocked[0].others[0].pending, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn build_inert() -> anyhow::Result<()> {
+        let builder = |policy| {
+            let mut builder = Builder::new();
+            builder.put(
+                Origin::Arguments,
+                IPrecursor::<Load<bool>>::new("!", "t", "A"),
+            );
+            builder.put(
+                Origin::Arguments,
+                arith::precursor(Op::Add, Overflow::Wrapping, "A", "A", "B"),
+            );
+            (builder, Diagnostics::new(policy))
+        };
+        let (mut b, mut diagnostics) = builder(Invalid::Error);
+        let err = b.deduct(&mut diagnostics).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "command line arguments: + is not defined for bool"
+        );
+
+        // the ill-typed `+` is left out, and so are the pipes only it used
+        let (mut b, mut diagnostics) = builder(Invalid::Warn);
+        b.deduct(&mut diagnostics)?;
+        let agents = b.build(&mut diagnostics)?;
+        assert_eq!(agents.len(), 2);
+        assert_eq!(diagnostics.warnings().len(), 1);
+        Executor::new(agents).run(None)
+    }
+
+    #[test]
+    fn deduct_rollback() -> anyhow::Result<()> {
+        // `D#+A` types `D` as usize before it trips on `A`, which must not reject `EE+D`
+        let mut diagnostics = Diagnostics::new(Invalid::Warn);
+        let source = "#0~!!HSA!ESED#+AEE+DDDDP";
+        let mut b = Builder::new();
+        b.put(
+            Origin::Arguments,
+            Box::new(CliArgAgentPrecursor::new(Vec::new())),
+        );
+        for instruction in parse(source, Syntax::Aligned, &mut diagnostics)? {
+            let precursor = operator::precursor(&instruction, Overflow::Wrapping)
+                .unwrap_or_else(|_| Box::new(Inert));
+            b.put(Origin::Instruction(instruction.span), precursor);
+        }
+        b.deduct(&mut diagnostics)?;
+        let warnings: Vec<String> = diagnostics
+            .warnings()
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(warnings, ["instruction 3 \"D#+A\""]);
+        assert_eq!(b.idx.ask("D"), Some(&TypeId::of::<String>()));
+        Ok(())
+    }
+
+    #[test]
+    fn run_self_loop() -> anyhow::Result<()> {
+        // the echo reads the pipe it writes: its own recv makes the room it sends into
+        let mut diagnostics = Diagnostics::new(Invalid::Error);
+        let mut b = Builder::new();
+        b.set_capacity(Capacity {
+            default: Some(1),
+            ..Capacity::unbounded()
+        });
+        b.put(
+            Origin::Arguments,
+            Box::new(CliArgAgentPrecursor::new(Vec::new())),
+        );
+        for instruction in parse("#0~!!HSA", Syntax::Aligned, &mut diagnostics)? {
+            let precursor = operator::precursor(&instruction, Overflow::Wrapping)?;
+            b.put(Origin::Instruction(instruction.span), precursor);
+        }
+        b.put(Origin::Arguments, IPrecursor::<Echo>::new("A", "", "A"));
+        b.deduct(&mut diagnostics)?;
+        let mut e = Executor::new(b.build(&mut diagnostics)?);
+        let err = e.run(Some(20)).unwrap_err();
+        assert_eq!(err.downcast::<StepLimitExceeded>().unwrap().steps, 20);
+        Ok(())
+    }
+
+    #[test]
+    fn untyped() -> anyhow::Result<()> {
+        // `+` takes its type from its pipes, and nothing else uses them
+        let mut diagnostics = Diagnostics::new(Invalid::Error);
+        let mut b = Builder::new();
+        b.put(
+            Origin::Arguments,
+            Box::new(CliArgAgentPrecursor::new(Vec::new())),
+        );
+        for instruction in parse("#0~!AB+C", Syntax::Aligned, &mut diagnostics)? {
+            let precursor = operator::precursor(&instruction, Overflow::Wrapping)?;
+            b.put(Origin::Instruction(instruction.span), precursor);
+        }
+        let err = b.deduct(&mut diagnostics).unwrap_err();
+        let err = err.downcast::<UntypedPipes>().unwrap();
+        let pipes: Vec<&str> = err.pipes.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(pipes, ["A", "B", "C"]);
+        assert_eq!(
+            err.to_string(),
+            "the type of 3 pipes could not be deduced:\
+            \n    \"A\" used by\n        instruction 1 \"AB+C\" at 1:5\
+            \n    \"B\" used by\n        instruction 1 \"AB+C\" at 1:5\
+            \n    \"C\" used by\n        instruction 1 \"AB+C\" at 1:5"
+        );
+        Ok(())
     }
 }
@@ -1,17 +1,36 @@
-use std::{any::TypeId, borrow::Borrow, collections::HashMap, fmt::Debug};
+use std::{
+    any::TypeId,
+    borrow::Borrow,
+    collections::HashMap,
+    error::Error,
+    fmt::{Debug, Display},
+    sync::Arc,
+};
 
-use anyhow::{anyhow, Context};
+use anyhow::Context;
 
 use crate::{
     agent::Agent,
-    pipe::{PipeBuilder, PipeReceiver, PipeSender},
+    pipe::{Element, PipeBuilder, PipeConfig, PipeReceiver, PipeSender, Usage},
+    span::Origin,
+    types,
 };
 use std::hash::Hash;
 
 #[derive(Debug)]
 pub struct PipeTypeIndex<K> {
     concrete_count: usize,
-    idx: HashMap<K, TypeId>,
+    idx: HashMap<K, Typed>,
+    /// the pipes in the order they were typed, for [`PipeTypeIndex::rollback`].
+    log: Vec<K>,
+}
+
+/// the type deduced for a pipe, and the precursor that required it first.
+#[derive(Debug)]
+struct Typed {
+    id: TypeId,
+    name: &'static str,
+    origin: Origin,
 }
 
 impl<K> PipeTypeIndex<K> {
@@ -19,6 +38,23 @@ impl<K> PipeTypeIndex<K> {
         Self {
             concrete_count: 0,
             idx: HashMap::new(),
+            log: Vec::new(),
+        }
+    }
+
+    /// a point to [`PipeTypeIndex::rollback`] to.
+    pub fn checkpoint(&self) -> usize {
+        self.log.len()
+    }
+
+    /// forgets every type fixed since `checkpoint`.
+    pub fn rollback(&mut self, checkpoint: usize)
+    where
+        K: Hash + Eq,
+    {
+        for k in self.log.drain(checkpoint..) {
+            self.idx.remove(&k);
+            self.concrete_count -= 1;
         }
     }
     pub fn ask<Q>(&self, k: &Q) -> Option<&TypeId>
@@ -26,52 +62,154 @@ impl<K> PipeTypeIndex<K> {
         Q: ?Sized + Hash + Eq,
         K: Borrow<Q> + Hash + Eq,
     {
-        self.idx.get(k)
+        self.idx.get(k).map(|typed| &typed.id)
     }
 
-    pub fn require_by_type_id<Q>(&mut self, k: &Q, type_id: TypeId) -> anyhow::Result<()>
+    /// fixes the type of pipe `k` to `type_id`, named `name`, on behalf of the precursor at `origin`.
+    /// fails with [`TypeConflict`] if another precursor fixed it to another type before.
+    pub fn require_by_type_id<Q>(
+        &mut self,
+        k: &Q,
+        type_id: TypeId,
+        name: &'static str,
+        origin: &Origin,
+    ) -> anyhow::Result<()>
     where
-        Q: ?Sized + Hash + Eq + ToOwned<Owned = K>,
+        Q: ?Sized + Hash + Eq + Display + ToOwned<Owned = K>,
         K: Borrow<Q> + Hash + Eq,
     {
         match self.idx.get(k) {
-            Some(ty) => {
-                if *ty != type_id {
-                    return Err(anyhow!("pipe type requirement conflict"));
+            Some(typed) => {
+                if typed.id != type_id {
+                    return Err(TypeConflict {
+                        pipe: k.to_string(),
+                        deduced: typed.name,
+                        deduced_by: typed.origin.clone(),
+                        required: name,
+                        required_by: origin.clone(),
+                    }
+                    .into());
                 }
             }
             None => {
-                self.idx.insert(k.to_owned(), type_id);
+                let typed = Typed {
+                    id: type_id,
+                    name,
+                    origin: origin.clone(),
+                };
+                self.idx.insert(k.to_owned(), typed);
+                self.log.push(k.to_owned());
                 self.concrete_count += 1;
             }
         }
         Ok(())
     }
 
-    pub fn require<T: 'static, Q>(&mut self, k: &Q) -> anyhow::Result<()>
+    pub fn require<T: 'static, Q>(&mut self, k: &Q, origin: &Origin) -> anyhow::Result<()>
     where
-        Q: ?Sized + Hash + Eq + ToOwned<Owned = K>,
+        Q: ?Sized + Hash + Eq + Display + ToOwned<Owned = K>,
         K: Borrow<Q> + Hash + Eq,
     {
-        self.require_by_type_id(k, TypeId::of::<T>())
+        self.require_by_type_id(k, TypeId::of::<T>(), types::name_of::<T>(), origin)
+    }
+
+    pub fn is_typed<Q>(&self, k: &Q) -> bool
+    where
+        Q: ?Sized + Hash + Eq,
+        K: Borrow<Q> + Hash + Eq,
+    {
+        self.idx.contains_key(k)
     }
 
     pub fn concrete_count(&self) -> usize {
         self.concrete_count
     }
-    pub fn generate(self) -> PipeIndex<K>
+    /// creates every pipe. `usage` is shared by all of them.
+    pub fn generate(self, capacity: &Capacity<K>, usage: &Arc<Usage>) -> PipeIndex<K>
     where
         K: Eq + Hash,
     {
         let idx: HashMap<K, PipeBuilder> = self
             .idx
             .into_iter()
-            .map(|(k, ty)| (k, PipeBuilder::new_by_type_id(ty)))
+            .map(|(k, typed)| {
+                let config = PipeConfig {
+                    capacity: capacity.of(&k),
+                    usage: usage.clone(),
+                };
+                (k, PipeBuilder::new_by_type_id(typed.id, config))
+            })
             .collect();
         PipeIndex { idx }
     }
 }
 
+/// two precursors require different types of the same pipe.
+#[derive(Debug)]
+pub struct TypeConflict {
+    pub pipe: String,
+    pub deduced: &'static str,
+    pub deduced_by: Origin,
+    pub required: &'static str,
+    pub required_by: Origin,
+}
+impl Display for TypeConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "pipe {:?} is {}, as required by {:#}, but {:#} requires {}",
+            self.pipe, self.deduced, self.deduced_by, self.required_by, self.required
+        )
+    }
+}
+impl Error for TypeConflict {}
+
+/// deduction reached its fixpoint with some pipes still untyped.
+#[derive(Debug)]
+pub struct UntypedPipes {
+    /// each untyped pipe with the precursors connected to it, in order of appearance.
+    pub pipes: Vec<(String, Vec<Origin>)>,
+}
+impl Display for UntypedPipes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the type of {} pipes could not be deduced:",
+            self.pipes.len()
+        )?;
+        for (pipe, origins) in self.pipes.iter() {
+            write!(f, "\n    {:?} used by", pipe)?;
+            for origin in origins.iter() {
+                write!(f, "\n        {:#}", origin)?;
+            }
+        }
+        Ok(())
+    }
+}
+impl Error for UntypedPipes {}
+
+/// how many unread elements each pipe may hold before its producers must wait.
+#[derive(Debug)]
+pub struct Capacity<K> {
+    /// for the pipes not listed in `pipes`. `None` is unbounded.
+    pub default: Option<usize>,
+    pub pipes: HashMap<K, usize>,
+}
+impl<K> Capacity<K> {
+    pub fn unbounded() -> Self {
+        Self {
+            default: None,
+            pipes: HashMap::new(),
+        }
+    }
+    pub fn of(&self, k: &K) -> Option<usize>
+    where
+        K: Eq + Hash,
+    {
+        self.pipes.get(k).copied().or(self.default)
+    }
+}
+
 pub struct PipeIndex<K> {
     idx: HashMap<K, PipeBuilder>,
 }
@@ -102,7 +240,7 @@ impl<K> PipeIndex<K> {
             .ask_type_id())
     }
 
-    pub fn require_sender<T: 'static, Q>(&self, k: &Q) -> anyhow::Result<PipeSender<T>>
+    pub fn require_sender<T: Element, Q>(&self, k: &Q) -> anyhow::Result<PipeSender<T>>
     where
         Q: ?Sized + Hash + Eq,
         K: Borrow<Q> + Hash + Eq,
@@ -112,7 +250,7 @@ impl<K> PipeIndex<K> {
             .context("pipe not implemented")?
             .require_sender()
     }
-    pub fn require_receiver<T: 'static, Q>(&self, k: &Q) -> anyhow::Result<PipeReceiver<T>>
+    pub fn require_receiver<T: Element, Q>(&self, k: &Q) -> anyhow::Result<PipeReceiver<T>>
     where
         Q: ?Sized + Hash + Eq,
         K: Borrow<Q> + Hash + Eq,
@@ -125,6 +263,48 @@ impl<K> PipeIndex<K> {
 }
 
 pub trait AgentPrecursor<K>: Debug {
-    fn deduct(&self, idx: &mut PipeTypeIndex<K>) -> anyhow::Result<()>;
-    fn build(self: Box<Self>, idx: &PipeIndex<K>) -> anyhow::Result<Box<dyn Agent>>;
+    /// every pipe the agent will be connected to.
+    fn pipes(&self) -> Vec<K>;
+    fn deduct(&self, idx: &mut PipeTypeIndex<K>, origin: &Origin) -> anyhow::Result<()>;
+    fn build(
+        self: Box<Self>,
+        idx: &PipeIndex<K>,
+        origin: &Origin,
+    ) -> anyhow::Result<Box<dyn Agent>>;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        diagnostic::Diagnostics,
+        parser::{parse, Syntax},
+        span::{render, Origin},
+    };
+
+    use super::{PipeTypeIndex, TypeConflict};
+
+    #[test]
+    fn conflict() {
+        let source = "!!xA!!SA";
+        let instructions = parse(source, Syntax::Aligned, &mut Diagnostics::default()).unwrap();
+        let [x, s] = [0, 1].map(|i| Origin::Instruction(instructions[i].span.clone()));
+
+        let mut idx = PipeTypeIndex::<String>::new();
+        idx.require::<u8, _>("A", &x).unwrap();
+        idx.require::<u8, _>("A", &x).unwrap();
+        let err = idx.require::<String, _>("A", &s).unwrap_err();
+        let conflict = err.downcast_ref::<TypeConflict>().unwrap();
+        assert_eq!((conflict.deduced, conflict.required), ("u8", "String"));
+        assert_eq!(conflict.pipe, "A");
+
+        // both instructions are named, the one requiring the other type is pointed at
+        assert_eq!(
+            render(&s.locate(err), Path::new("a.ppop")).unwrap(),
+            "pipe \"A\" is u8, as required by instruction 0 \"!!xA\" at 1:1, \
+            but instruction 1 \"!!SA\" at 1:5 requires String\
+            \n --> a.ppop:1:5\n  |\n1 | !!xA!!SA\n  |     ^^^^"
+        );
+    }
 }
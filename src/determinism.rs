@@ -0,0 +1,296 @@
+use std::{
+    env::current_exe,
+    fmt::Display,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{anyhow, Context};
+
+/// what a single run of a script left behind.
+#[derive(Debug, PartialEq, Eq)]
+struct Outcome {
+    stdout: String,
+    stderr: String,
+    status: Option<i32>,
+}
+
+/// options of a run that `ppop check-determinism` passes on to every seed.
+const FORWARDED: [&str; 7] = [
+    "--max-steps",
+    "--capacity",
+    "--overflow",
+    "--invalid",
+    "--max-buffered",
+    "--max-produced",
+    "--max-firings",
+];
+
+/// parameters of `ppop check-determinism`.
+pub struct Check {
+    pub script: PathBuf,
+    pub args: Vec<String>,
+    pub runs: u64,
+    /// the options in [`FORWARDED`], with their values.
+    pub options: Vec<String>,
+}
+
+impl Check {
+    /// parses `[-n N] [run options...] script.ppop [args...]`.
+    pub fn parse(mut it: impl Iterator<Item = String>) -> anyhow::Result<Self> {
+        let mut runs = 100;
+        let mut options = Vec::new();
+        let script: PathBuf = loop {
+            let arg = it.next().ok_or(anyhow!("script file not specified"))?;
+            match arg.as_str() {
+                "-n" => {
+                    let n = it.next().ok_or(anyhow!("-n requires a value"))?;
+                    runs = n.parse().context(anyhow!("invalid -n: {}", n))?;
+                }
+                option if FORWARDED.contains(&option) => {
+                    let value = it.next().ok_or(anyhow!("{} requires a value", option))?;
+                    options.extend([arg, value]);
+                }
+                "--stats" | "--threads" | "--async" | "--schedule" | "--seed" => {
+                    return Err(anyhow!("{} cannot be used with check-determinism", arg));
+                }
+                _ => break arg.into(),
+            }
+        };
+        Ok(Self {
+            script,
+            args: it.collect(),
+            runs,
+            options,
+        })
+    }
+
+    /// runs the script once per seed in `0..runs` under `--schedule random`, groups
+    /// the seeds by outcome, and diffs every other outcome with the one of seed 0.
+    pub fn run(&self) -> anyhow::Result<()> {
+        if self.runs == 0 {
+            return Err(anyhow!("-n must be at least 1"));
+        }
+        let exe = current_exe().context("failed to locate the ppop executable")?;
+        let mut groups: Vec<(Outcome, Vec<u64>)> = Vec::new();
+        for seed in 0..self.runs {
+            let outcome = self.run_seed(&exe, seed)?;
+            match groups.iter_mut().find(|(o, _)| *o == outcome) {
+                Some((_, seeds)) => seeds.push(seed),
+                None => groups.push((outcome, vec![seed])),
+            }
+        }
+        if groups.len() == 1 {
+            println!("identical outputs under {} schedules", self.runs);
+            return Ok(());
+        }
+        println!(
+            "{} distinct outputs under {} schedules:",
+            groups.len(),
+            self.runs
+        );
+        for (k, (_, seeds)) in groups.iter().enumerate() {
+            println!("#{}: seeds {}", k, Seeds(seeds));
+        }
+        let (first, _) = &groups[0];
+        for (k, (outcome, _)) in groups.iter().enumerate().skip(1) {
+            println!("#0 -> #{}:", k);
+            print!("{}", OutcomeDiff(first, outcome));
+        }
+        Err(anyhow!(
+            "{} is not deterministic",
+            self.script.to_string_lossy()
+        ))
+    }
+
+    fn run_seed(&self, exe: &Path, seed: u64) -> anyhow::Result<Outcome> {
+        let mut command = Command::new(exe);
+        command.args(["--schedule", "random", "--seed", &seed.to_string()]);
+        command.args(&self.options);
+        // backtraces would differ between runs for no reason of the script
+        let output = command
+            .arg(&self.script)
+            .args(&self.args)
+            .env("RUST_BACKTRACE", "0")
+            .env("RUST_LIB_BACKTRACE", "0")
+            .output()
+            .context(anyhow!("failed to run seed {}", seed))?;
+        Ok(Outcome {
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            status: output.status.code(),
+        })
+    }
+}
+
+struct OutcomeDiff<'a>(&'a Outcome, &'a Outcome);
+impl Display for OutcomeDiff<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self(a, b) = self;
+        if a.status != b.status {
+            writeln!(f, "exit status: {:?} != {:?}", a.status, b.status)?;
+        }
+        for (name, a, b) in [
+            ("stdout", &a.stdout, &b.stdout),
+            ("stderr", &a.stderr, &b.stderr),
+        ] {
+            if a != b {
+                writeln!(f, "--- {}", name)?;
+                for line in diff(a, b) {
+                    writeln!(f, "{}", line)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// seeds in ascending order, consecutive ones written as a range.
+struct Seeds<'a>(&'a [u64]);
+impl Display for Seeds<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut seeds = self.0.iter().copied().peekable();
+        let mut first = true;
+        while let Some(start) = seeds.next() {
+            let mut end = start;
+            while seeds.next_if_eq(&(end + 1)).is_some() {
+                end += 1;
+            }
+            if !first {
+                write!(f, ", ")?;
+            }
+            first = false;
+            match end - start {
+                0 => write!(f, "{}", start)?,
+                1 => write!(f, "{}, {}", start, end)?,
+                _ => write!(f, "{}..={}", start, end)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// the most cells of the lcs table [`diff`] builds. past it only the first
+/// differing lines are reported.
+const MAX_DIFF_CELLS: usize = 1 << 22;
+
+/// line diff of `a` and `b` by their longest common subsequence.
+/// lines only in `a` are prefixed with `-`, lines only in `b` with `+`.
+fn diff(a: &str, b: &str) -> Vec<String> {
+    let a: Vec<&str> = a.lines().collect();
+    let b: Vec<&str> = b.lines().collect();
+    // the common head and tail need no table
+    let head = a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count();
+    let tail = a[head..]
+        .iter()
+        .rev()
+        .zip(b[head..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a_mid, b_mid) = (&a[head..a.len() - tail], &b[head..b.len() - tail]);
+
+    let mut lines: Vec<String> = a[..head].iter().map(|l| format!(" {}", l)).collect();
+    if (a_mid.len() + 1).saturating_mul(b_mid.len() + 1) > MAX_DIFF_CELLS {
+        lines.extend(a_mid.first().map(|l| format!("-{}", l)));
+        lines.extend(b_mid.first().map(|l| format!("+{}", l)));
+        lines.push(format!(
+            "... {} and {} more lines left undiffed",
+            a_mid.len().saturating_sub(1),
+            b_mid.len().saturating_sub(1)
+        ));
+    } else {
+        lines.extend(lcs_diff(a_mid, b_mid));
+    }
+    lines.extend(a[a.len() - tail..].iter().map(|l| format!(" {}", l)));
+    lines
+}
+
+fn lcs_diff(a: &[&str], b: &[&str]) -> Vec<String> {
+    // lcs[i][j]: length of the common subsequence of a[i..] and b[j..]
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            lines.push(format!(" {}", a[i]));
+            i += 1;
+            j += 1;
+        } else if j == b.len() || (i < a.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(format!("-{}", a[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+{}", b[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, Check, Seeds};
+
+    #[test]
+    fn line_diff() {
+        assert_eq!(
+            diff("a\nb\nc\n", "a\nc\nd\n"),
+            [" a", "-b", " c", "+d"].map(String::from)
+        );
+        assert_eq!(diff("HW", "WH"), ["-HW", "+WH"].map(String::from));
+    }
+
+    #[test]
+    fn long_diff() {
+        // too long for a table: only the first differing lines are shown
+        let a: String = (0..3000).map(|i| format!("{}\n", i)).collect();
+        let b: String = (0..3000).rev().map(|i| format!("{}\n", i)).collect();
+        let lines = diff(&format!("x\n{}y\n", a), &format!("x\n{}y\n", b));
+        assert_eq!(
+            lines,
+            [
+                " x",
+                "-0",
+                "+2999",
+                "... 2999 and 2999 more lines left undiffed",
+                " y"
+            ]
+            .map(String::from)
+        );
+    }
+
+    #[test]
+    fn parse() -> anyhow::Result<()> {
+        let args = [
+            "-n",
+            "5",
+            "--capacity",
+            "A=1",
+            "--invalid",
+            "error",
+            "a.ppop",
+            "x",
+        ];
+        let check = Check::parse(args.into_iter().map(String::from))?;
+        assert_eq!(check.runs, 5);
+        assert_eq!(check.options, ["--capacity", "A=1", "--invalid", "error"]);
+        assert_eq!(check.args, ["x"]);
+        let args = ["--threads", "2", "a.ppop"];
+        assert!(Check::parse(args.into_iter().map(String::from)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn seeds() {
+        assert_eq!(Seeds(&[0, 1, 2, 3, 5, 7, 8]).to_string(), "0..=3, 5, 7, 8");
+    }
+}
@@ -0,0 +1,60 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+
+/// what becomes of a tuple that does not make a valid instruction: a partial tuple,
+/// an unknown operator, an ill-typed tuple or a malformed literal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Invalid {
+    /// it runs as an inert agent, silently.
+    Inert,
+    /// it runs as an inert agent, and a warning is printed.
+    #[default]
+    Warn,
+    /// the script is rejected.
+    Error,
+}
+impl FromStr for Invalid {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "inert" => Ok(Invalid::Inert),
+            "warn" => Ok(Invalid::Warn),
+            "error" => Ok(Invalid::Error),
+            _ => Err(anyhow!("expected inert, warn or error")),
+        }
+    }
+}
+
+/// the invalid tuples met while building a script.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    policy: Invalid,
+    warnings: Vec<anyhow::Error>,
+}
+impl Diagnostics {
+    pub fn new(policy: Invalid) -> Self {
+        Self {
+            policy,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// reports why a tuple is invalid. fails with `e` under [`Invalid::Error`],
+    /// otherwise the caller goes on with an inert agent in its place.
+    pub fn invalid(&mut self, e: anyhow::Error) -> anyhow::Result<()> {
+        match self.policy {
+            Invalid::Inert => Ok(()),
+            Invalid::Warn => {
+                self.warnings.push(e);
+                Ok(())
+            }
+            Invalid::Error => Err(e),
+        }
+    }
+
+    pub fn warnings(&self) -> &[anyhow::Error] {
+        &self.warnings
+    }
+}
@@ -1,79 +1,245 @@
 mod agent;
 mod controller;
-//mod type_enum;
 //mod op;
 //mod op_imm;
 mod cli_arg;
 mod deduct;
+mod determinism;
+mod diagnostic;
 mod operator;
+mod parallel;
+mod parser;
 mod pipe;
+mod runtime;
+mod schedule;
+mod span;
+mod types;
 //mod pipe_board;
 
-use std::{env::args, fs::read_to_string, path::PathBuf};
+use std::{
+    env::args,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
 
 use anyhow::{anyhow, Context};
 use cli_arg::CliArgAgentPrecursor;
-use controller::Builder;
-use operator::{add, delete::DeletePrecursor, load::Load, stdio::SxPrintf, IPrecursor};
-use unicode_segmentation::UnicodeSegmentation;
+use controller::{
+    Builder, Execute, Executor, Inert, LimitExceeded, Limits, Stalled, StepLimitExceeded,
+};
+use deduct::Capacity;
+use determinism::Check;
+use diagnostic::{Diagnostics, Invalid};
+use operator::arith::Overflow;
+use parallel::ParallelExecutor;
+use parser::Syntax;
+use runtime::AsyncExecutor;
+use span::Origin;
+
+enum Mode {
+    /// `None` steps agents in wake order, `Some(seed)` in a random order.
+    Sequential(Option<u64>),
+    Parallel(usize),
+    Async,
+}
 
 struct Param {
     script: PathBuf,
+    max_steps: Option<usize>,
+    stats: bool,
+    mode: Mode,
+    capacity: Capacity<String>,
+    limits: Limits,
+    /// integer overflow of the operators without an explicit variant.
+    overflow: Overflow,
+    invalid: Invalid,
 }
 
 fn parse_args() -> anyhow::Result<(Param, Vec<String>)> {
     let it = args();
     let mut it = it.skip(1);
-    let script: PathBuf = it
-        .next()
-        .ok_or(anyhow!("script file not specified"))?
-        .into();
+    let mut max_steps = None;
+    let mut stats = false;
+    let mut mode = None;
+    let mut random = false;
+    let mut seed = None;
+    let mut capacity = Capacity::unbounded();
+    let mut limits = Limits::default();
+    let mut overflow = Overflow::default();
+    let mut invalid = Invalid::default();
+    let script: PathBuf = loop {
+        let arg = it.next().ok_or(anyhow!("script file not specified"))?;
+        match arg.as_str() {
+            "--max-steps" => {
+                let n = it.next().ok_or(anyhow!("--max-steps requires a value"))?;
+                max_steps = Some(n.parse().context(anyhow!("invalid --max-steps: {}", n))?);
+            }
+            "--stats" => stats = true,
+            "--threads" => {
+                let n = it.next().ok_or(anyhow!("--threads requires a value"))?;
+                let n = n.parse().context(anyhow!("invalid --threads: {}", n))?;
+                if n == 0 {
+                    return Err(anyhow!("invalid --threads: {}: must be at least 1", n));
+                }
+                if mode.replace(Mode::Parallel(n)).is_some() {
+                    return Err(anyhow!("--threads and --async are exclusive"));
+                }
+            }
+            "--async" => {
+                if mode.replace(Mode::Async).is_some() {
+                    return Err(anyhow!("--threads and --async are exclusive"));
+                }
+            }
+            "--schedule" => {
+                let s = it.next().ok_or(anyhow!("--schedule requires a value"))?;
+                random = match s.as_str() {
+                    "woken" => false,
+                    "random" => true,
+                    _ => return Err(anyhow!("unknown --schedule: {}", s)),
+                };
+            }
+            "--capacity" => {
+                let s = it.next().ok_or(anyhow!("--capacity requires a value"))?;
+                // either `N` for every pipe or `X=N` for pipe `X`
+                let (pipe, n) = match s.rsplit_once('=') {
+                    Some((pipe, n)) => (Some(pipe), n),
+                    None => (None, s.as_str()),
+                };
+                let n: usize = n.parse().context(anyhow!("invalid --capacity: {}", s))?;
+                if n == 0 {
+                    return Err(anyhow!("invalid --capacity: {}: must be at least 1", s));
+                }
+                match pipe {
+                    Some(pipe) => {
+                        capacity.pipes.insert(pipe.to_string(), n);
+                    }
+                    None => capacity.default = Some(n),
+                }
+            }
+            "--max-buffered" | "--max-produced" | "--max-firings" => {
+                let n = it.next().ok_or(anyhow!("{} requires a value", arg))?;
+                let n = Some(n.parse().context(anyhow!("invalid {}: {}", arg, n))?);
+                match arg.as_str() {
+                    "--max-buffered" => limits.buffered = n,
+                    "--max-produced" => limits.produced = n,
+                    _ => limits.fired = n,
+                }
+            }
+            "--overflow" => {
+                let s = it.next().ok_or(anyhow!("--overflow requires a value"))?;
+                overflow = s.parse().context(anyhow!("invalid --overflow: {}", s))?;
+            }
+            "--invalid" => {
+                let s = it.next().ok_or(anyhow!("--invalid requires a value"))?;
+                invalid = s.parse().context(anyhow!("invalid --invalid: {}", s))?;
+            }
+            "--seed" => {
+                let n = it.next().ok_or(anyhow!("--seed requires a value"))?;
+                seed = Some(n.parse().context(anyhow!("invalid --seed: {}", n))?);
+            }
+            _ => break arg.into(),
+        }
+    };
+    if seed.is_some() && !random {
+        return Err(anyhow!("--seed requires --schedule random"));
+    }
+    let mode = match mode {
+        None => Mode::Sequential(random.then(|| seed.unwrap_or(0))),
+        Some(_) if random => {
+            return Err(anyhow!(
+                "--schedule random is not available with --threads or --async"
+            ))
+        }
+        Some(_) if !limits.is_unlimited() => {
+            return Err(anyhow!(
+                "--max-buffered, --max-produced and --max-firings are not available with --threads or --async"
+            ))
+        }
+        Some(mode) => mode,
+    };
     let args: Vec<String> = it.collect();
-    Ok((Param { script }, args))
+    Ok((
+        Param {
+            script,
+            max_steps,
+            stats,
+            mode,
+            capacity,
+            limits,
+            overflow,
+            invalid,
+        },
+        args,
+    ))
+}
+
+fn main() -> ExitCode {
+    if args().nth(1).as_deref() == Some("check-determinism") {
+        return exit(
+            Check::parse(args().skip(2)).and_then(|check| check.run()),
+            None,
+        );
+    }
+    match parse_args() {
+        Ok((param, args)) => {
+            let script = param.script.clone();
+            exit(run(param, args), Some(&script))
+        }
+        Err(e) => exit(Err(e), None),
+    }
 }
 
-fn main() -> anyhow::Result<()> {
-    let (param, args) = parse_args()?;
+/// reports the error, if any, with the source of `script` it was located at.
+fn exit(result: anyhow::Result<()>, script: Option<&Path>) -> ExitCode {
+    let Err(e) = result else {
+        return ExitCode::SUCCESS;
+    };
+    match script.and_then(|script| span::render(&e, script)) {
+        Some(rendered) => eprintln!("Error: {}", rendered),
+        None => eprintln!("Error: {:?}", e),
+    }
+    if e.is::<StepLimitExceeded>() {
+        ExitCode::from(2)
+    } else if e.is::<Stalled>() {
+        ExitCode::from(3)
+    } else if e.is::<LimitExceeded>() {
+        ExitCode::from(4)
+    } else {
+        ExitCode::FAILURE
+    }
+}
 
+fn run(param: Param, args: Vec<String>) -> anyhow::Result<()> {
     //println!("exec {}", script.to_str().unwrap());
 
-    let s = read_to_string(param.script).context(anyhow!("failed to read script"))?;
-    let gv = s.graphemes(true).collect::<Vec<&str>>();
-    if gv.len() % 4 != 0 {
-        return Err(anyhow!("script alignment invalid"));
-    }
+    let s = read_to_string(&param.script).context(anyhow!("failed to read script"))?;
+    let mut diagnostics = Diagnostics::new(param.invalid);
+    let instructions = parser::parse(&s, Syntax::of(&param.script, &s), &mut diagnostics)?;
 
     let mut builder = Builder::new();
+    builder.set_capacity(param.capacity);
 
-    builder.put(Box::new(CliArgAgentPrecursor::new(args)));
-
-    for i in 0..gv.len() / 4 {
-        //parse
-        let li1 = gv[4 * i];
-        let li2 = gv[4 * i + 1];
-        let lop = gv[4 * i + 2];
-        let lo = gv[4 * i + 3];
-
-        builder.put(match lop {
-            "+" | "加" => add::precursor(li1, li2, lo)?,
-            "S" | "字" => IPrecursor::<Load<String>>::new(li1, li2, lo),
-            "~" => Box::new(DeletePrecursor::new(li1, lo)),
-            // "x" => replace::build::<u8>(li1, li2, lo, &mut self.board)?,
-            // "i" | "整" => replace::build::<i64>(li1, li2, lo, &mut self.board)?,
-            // "u" => replace::build::<u64>(li1, li2, lo, &mut self.board)?,
-            // "f" => replace::build::<f64>(li1, li2, lo, &mut self.board)?,
-            // "U" => replace::build::<usize>(li1, li2, lo, &mut self.board)?,
-            // "I" => replace::build::<isize>(li1, li2, lo, &mut self.board)?,
-            "P" | "印" => IPrecursor::<SxPrintf>::new(li1, li2, lo),
-            _ => return Err(anyhow!("operator {} is not registered", lop)),
-        });
+    builder.put(Origin::Arguments, Box::new(CliArgAgentPrecursor::new(args)));
+
+    for instruction in instructions {
+        let precursor = operator::precursor(&instruction, param.overflow);
+        let origin = Origin::Instruction(instruction.span);
+        let precursor = match precursor {
+            Ok(precursor) => precursor,
+            Err(e) => {
+                diagnostics.invalid(origin.locate(e))?;
+                Box::new(Inert)
+            }
+        };
+        builder.put(origin, precursor);
     }
 
     // println!("builder: {:?}", builder);
 
     // println!("deduct");
 
-    builder.deduct()?;
+    builder.deduct(&mut diagnostics)?;
 
     //builder.deduct()?;
 
@@ -81,21 +247,35 @@ fn main() -> anyhow::Result<()> {
 
     // println!("build");
 
-    let mut executor = builder.build()?;
+    let usage = builder.usage();
+    let agents = builder.build(&mut diagnostics)?;
+    for warning in diagnostics.warnings() {
+        match span::render(warning, &param.script) {
+            Some(rendered) => eprintln!("warning: {}", rendered),
+            None => eprintln!("warning: {:#}", warning),
+        }
+    }
+    let mut executor: Box<dyn Execute> = match param.mode {
+        Mode::Sequential(seed) => {
+            let mut executor = Executor::new(agents).limited(param.limits, usage);
+            if let Some(seed) = seed {
+                executor = executor.randomized(seed);
+            }
+            Box::new(executor)
+        }
+        Mode::Parallel(threads) => Box::new(ParallelExecutor::new(agents, threads)),
+        Mode::Async => Box::new(AsyncExecutor::new(agents)),
+    };
 
     // println!("exe");
 
     // println!("{:?}", executor);
 
-    let mut t: usize = 0;
-
-    while {
-        //println!("t={}", t);
-        //println!("{:?}", executor);
-        executor.step() && t < 5
-    } {
-        t += 1;
+    let result = executor.run(param.max_steps);
+    if param.stats {
+        eprintln!("{}", executor.stats());
     }
+    result?;
 
     // println!("{:?}", executor);
 
@@ -0,0 +1,344 @@
+use std::{any::TypeId, fmt::Debug, marker::PhantomData, str::FromStr};
+
+use anyhow::anyhow;
+
+use crate::{
+    agent::Agent,
+    deduct::{AgentPrecursor, PipeIndex, PipeTypeIndex},
+    pipe::Element,
+    span::Origin,
+    types,
+};
+
+use super::{YBuildable, YOp};
+
+/// the arithmetic operators of the README.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+impl Op {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Op::Add => "+",
+            Op::Sub => "-",
+            Op::Mul => "*",
+            Op::Div => "/",
+            Op::Rem => "%",
+        }
+    }
+}
+
+/// what integer arithmetic does when the result does not fit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// wrap around, as two's complement does.
+    #[default]
+    Wrapping,
+    /// clamp to the bounds of the type.
+    Saturating,
+    /// fail the instruction.
+    Checked,
+}
+impl FromStr for Overflow {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wrapping" => Ok(Overflow::Wrapping),
+            "saturating" => Ok(Overflow::Saturating),
+            "checked" => Ok(Overflow::Checked),
+            _ => Err(anyhow!("unknown overflow semantics: {}", s)),
+        }
+    }
+}
+
+/// element types arithmetic is defined for: every number of the README, and `String` for `+`.
+pub trait Arith: Element + Sized {
+    fn supports(_op: Op) -> bool {
+        true
+    }
+    fn apply(op: Op, overflow: Overflow, lhs: &Self, rhs: &Self) -> anyhow::Result<Self>;
+}
+macro_rules! arith_int {
+    ($($t:ty),*) => {
+        $(impl Arith for $t {
+            fn apply(op: Op, overflow: Overflow, lhs: &Self, rhs: &Self) -> anyhow::Result<Self> {
+                let (l, r) = (*lhs, *rhs);
+                if matches!(op, Op::Div | Op::Rem) && r == 0 {
+                    return Err(anyhow!("{} {} {}: division by zero", l, op.symbol(), r));
+                }
+                let checked = match op {
+                    Op::Add => l.checked_add(r),
+                    Op::Sub => l.checked_sub(r),
+                    Op::Mul => l.checked_mul(r),
+                    Op::Div => l.checked_div(r),
+                    Op::Rem => l.checked_rem(r),
+                };
+                Ok(match (checked, overflow) {
+                    (Some(v), _) => v,
+                    (None, Overflow::Wrapping) => match op {
+                        Op::Add => l.wrapping_add(r),
+                        Op::Sub => l.wrapping_sub(r),
+                        Op::Mul => l.wrapping_mul(r),
+                        Op::Div => l.wrapping_div(r),
+                        Op::Rem => l.wrapping_rem(r),
+                    },
+                    (None, Overflow::Saturating) => match op {
+                        Op::Add => l.saturating_add(r),
+                        Op::Sub => l.saturating_sub(r),
+                        Op::Mul => l.saturating_mul(r),
+                        Op::Div => l.saturating_div(r),
+                        // only MIN % -1 overflows, and its remainder is 0
+                        Op::Rem => l.wrapping_rem(r),
+                    },
+                    (None, Overflow::Checked) => {
+                        return Err(anyhow!(
+                            "{} {} {}: overflows {}",
+                            l,
+                            op.symbol(),
+                            r,
+                            types::name_of::<$t>()
+                        ))
+                    }
+                })
+            }
+        })*
+    };
+}
+arith_int!(u8, u64, i64, usize, isize);
+impl Arith for f64 {
+    fn apply(op: Op, _overflow: Overflow, lhs: &Self, rhs: &Self) -> anyhow::Result<Self> {
+        Ok(match op {
+            Op::Add => lhs + rhs,
+            Op::Sub => lhs - rhs,
+            Op::Mul => lhs * rhs,
+            Op::Div => lhs / rhs,
+            Op::Rem => lhs % rhs,
+        })
+    }
+}
+impl Arith for String {
+    fn supports(op: Op) -> bool {
+        op == Op::Add
+    }
+    fn apply(op: Op, _overflow: Overflow, lhs: &Self, rhs: &Self) -> anyhow::Result<Self> {
+        match op {
+            Op::Add => Ok(format!("{}{}", lhs, rhs)),
+            _ => Err(anyhow!("{} is not defined for String", op.symbol())),
+        }
+    }
+}
+
+pub struct Binary<T> {
+    op: Op,
+    overflow: Overflow,
+    ph: PhantomData<T>,
+}
+impl<T> Debug for Binary<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Binary")
+            .field("op", &self.op)
+            .field("overflow", &self.overflow)
+            .field("ph", &self.ph)
+            .finish()
+    }
+}
+impl<T> Binary<T> {
+    pub fn new(op: Op, overflow: Overflow) -> Self {
+        Self {
+            op,
+            overflow,
+            ph: PhantomData,
+        }
+    }
+}
+
+impl<T: Arith> YOp for Binary<T> {
+    type Input1 = T;
+
+    type Input2 = T;
+
+    type Output = T;
+
+    fn exec(
+        &self,
+        e1: &Self::Input1,
+        e2: &Self::Input2,
+    ) -> anyhow::Result<impl IntoIterator<Item = <Self as YOp>::Output>> {
+        Ok([T::apply(self.op, self.overflow, e1, e2)?])
+    }
+}
+
+type BuildResult = anyhow::Result<Box<dyn Agent>>;
+
+/// arithmetic specialized to one element type.
+struct ArithImpl {
+    id: TypeId,
+    supports: fn(Op) -> bool,
+    deduct: fn(&ArithPrecursor, &mut PipeTypeIndex<String>, &Origin) -> anyhow::Result<()>,
+    build: fn(ArithPrecursor, &PipeIndex<String>) -> BuildResult,
+}
+impl ArithImpl {
+    fn of<T: Arith>() -> Self {
+        Self {
+            id: TypeId::of::<T>(),
+            supports: T::supports,
+            deduct: |p, idx, origin| {
+                idx.require::<T, _>(&p.pi1, origin)?;
+                idx.require::<T, _>(&p.pi2, origin)?;
+                idx.require::<T, _>(&p.po, origin)
+            },
+            build: |p, idx| {
+                let op = Binary::<T>::new(p.op, p.overflow);
+                Ok(Box::new(Binary::build(p.pi1, p.pi2, p.po, op, idx)?))
+            },
+        }
+    }
+}
+fn impls() -> [ArithImpl; 7] {
+    [
+        ArithImpl::of::<u8>(),
+        ArithImpl::of::<u64>(),
+        ArithImpl::of::<i64>(),
+        ArithImpl::of::<f64>(),
+        ArithImpl::of::<usize>(),
+        ArithImpl::of::<isize>(),
+        ArithImpl::of::<String>(),
+    ]
+}
+fn find_impl(op: Op, id: TypeId) -> anyhow::Result<ArithImpl> {
+    impls()
+        .into_iter()
+        .find(|i| i.id == id && (i.supports)(op))
+        .ok_or_else(|| {
+            let name = types::lookup(id).map_or("this type", |t| t.name);
+            anyhow!("{} is not defined for {}", op.symbol(), name)
+        })
+}
+
+pub fn precursor(
+    op: Op,
+    overflow: Overflow,
+    pi1: &str,
+    pi2: &str,
+    po: &str,
+) -> Box<dyn AgentPrecursor<String>> {
+    let pi1 = pi1.to_string();
+    let pi2 = pi2.to_string();
+    let po = po.to_string();
+    Box::new(ArithPrecursor {
+        op,
+        overflow,
+        pi1,
+        pi2,
+        po,
+    })
+}
+
+#[derive(Debug)]
+struct ArithPrecursor {
+    op: Op,
+    overflow: Overflow,
+    pi1: String,
+    pi2: String,
+    po: String,
+}
+impl AgentPrecursor<String> for ArithPrecursor {
+    fn pipes(&self) -> Vec<String> {
+        vec![self.pi1.clone(), self.pi2.clone(), self.po.clone()]
+    }
+    /// the three pipes share one type: whichever of them is typed first decides it.
+    fn deduct(&self, idx: &mut PipeTypeIndex<String>, origin: &Origin) -> anyhow::Result<()> {
+        let known = [&self.pi1, &self.pi2, &self.po]
+            .into_iter()
+            .find_map(|pipe| idx.ask(pipe).copied());
+        match known {
+            Some(id) => (find_impl(self.op, id)?.deduct)(self, idx, origin),
+            None => Ok(()),
+        }
+    }
+
+    fn build(
+        self: Box<Self>,
+        idx: &PipeIndex<String>,
+        _origin: &Origin,
+    ) -> anyhow::Result<Box<dyn Agent>> {
+        let id = idx.ask(&self.po)?;
+        (find_impl(self.op, id)?.build)(*self, idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::TypeId;
+
+    use crate::{deduct::PipeTypeIndex, span::Origin, types};
+
+    use super::{impls, precursor, Arith, Op, Overflow::*};
+
+    #[test]
+    fn registry() {
+        // every registered type but bool and () has arithmetic, in the order of the registry
+        let arith: Vec<_> = impls()
+            .iter()
+            .map(|i| types::lookup(i.id).unwrap().name)
+            .collect();
+        let registered: Vec<_> = types::all()
+            .iter()
+            .map(|t| t.name)
+            .filter(|name| !["bool", "()"].contains(name))
+            .collect();
+        assert_eq!(arith, registered);
+    }
+
+    #[test]
+    fn infer() {
+        let add = precursor(Op::Add, Wrapping, "A", "B", "C");
+        let mut idx = PipeTypeIndex::<String>::new();
+        idx.require::<i64, _>("C", &Origin::Arguments).unwrap();
+        add.deduct(&mut idx, &Origin::Arguments).unwrap();
+        assert_eq!(idx.ask("A"), Some(&TypeId::of::<i64>()));
+        assert_eq!(idx.ask("B"), Some(&TypeId::of::<i64>()));
+
+        let mut idx = PipeTypeIndex::<String>::new();
+        idx.require::<bool, _>("B", &Origin::Arguments).unwrap();
+        let err = add.deduct(&mut idx, &Origin::Arguments).unwrap_err();
+        assert_eq!(err.to_string(), "+ is not defined for bool");
+
+        let sub = precursor(Op::Sub, Wrapping, "A", "B", "C");
+        let mut idx = PipeTypeIndex::<String>::new();
+        idx.require::<String, _>("A", &Origin::Arguments).unwrap();
+        let err = sub.deduct(&mut idx, &Origin::Arguments).unwrap_err();
+        assert_eq!(err.to_string(), "- is not defined for String");
+    }
+
+    #[test]
+    fn apply() {
+        assert_eq!(i64::apply(Op::Rem, Wrapping, &-7, &2).unwrap(), -1);
+        assert_eq!(
+            f64::apply(Op::Div, Checked, &1.0, &0.0).unwrap(),
+            f64::INFINITY
+        );
+        let err = usize::apply(Op::Div, Wrapping, &1, &0).unwrap_err();
+        assert_eq!(err.to_string(), "1 / 0: division by zero");
+    }
+
+    #[test]
+    fn overflow() {
+        assert_eq!(u8::apply(Op::Add, Wrapping, &250, &10).unwrap(), 4);
+        assert_eq!(u8::apply(Op::Add, Saturating, &250, &10).unwrap(), 255);
+        assert_eq!(u64::apply(Op::Sub, Saturating, &1, &2).unwrap(), 0);
+        assert_eq!(
+            i64::apply(Op::Div, Saturating, &i64::MIN, &-1).unwrap(),
+            i64::MAX
+        );
+        assert_eq!(i64::apply(Op::Rem, Checked, &-7, &2).unwrap(), -1);
+        let err = u8::apply(Op::Mul, Checked, &16, &16).unwrap_err();
+        assert_eq!(err.to_string(), "16 * 16: overflows u8");
+    }
+}
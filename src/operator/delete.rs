@@ -2,7 +2,7 @@ use std::{fmt::Debug, marker::PhantomData};
 
 use anyhow::anyhow;
 
-use crate::deduct::AgentPrecursor;
+use crate::{deduct::AgentPrecursor, pipe::Element, span::Origin};
 
 use super::{IBuildable, IOp};
 
@@ -15,7 +15,7 @@ impl<D> Debug for Delete<D> {
     }
 }
 
-impl<D: 'static> IOp for Delete<D> {
+impl<D: Element> IOp for Delete<D> {
     type Input = D;
 
     type Output = ();
@@ -45,13 +45,21 @@ impl DeletePrecursor {
     }
 }
 impl AgentPrecursor<String> for DeletePrecursor {
-    fn deduct(&self, idx: &mut crate::deduct::PipeTypeIndex<String>) -> anyhow::Result<()> {
-        idx.require::<(), _>(&self.po)
+    fn pipes(&self) -> Vec<String> {
+        vec![self.pi.clone(), self.po.clone()]
+    }
+    fn deduct(
+        &self,
+        idx: &mut crate::deduct::PipeTypeIndex<String>,
+        origin: &Origin,
+    ) -> anyhow::Result<()> {
+        idx.require::<(), _>(&self.po, origin)
     }
 
     fn build(
         self: Box<Self>,
         idx: &crate::deduct::PipeIndex<String>,
+        _origin: &Origin,
     ) -> anyhow::Result<Box<dyn crate::agent::Agent>> {
         if idx.ask_to_be::<usize, _>(&self.pi)? {
             //println!("ill");
@@ -1,8 +1,10 @@
 use std::fmt::Debug;
 
 use super::IOp;
+use crate::{pipe::Element, types};
 use anyhow::{anyhow, Context};
 
+/// the immediate of a load, given with its escape sequences replaced, see `parser::fields`.
 pub trait GraphemeImmediate: Sized {
     fn parse(_imm: String) -> anyhow::Result<Self> {
         Err(anyhow!("not implemented"))
@@ -20,7 +22,7 @@ impl<M> Debug for Load<M> {
         f.debug_struct("Load").field("imm", &"(IMM)").finish()
     }
 }
-impl<M: 'static + GraphemeImmediate> IOp for Load<M> {
+impl<M: Element + GraphemeImmediate> IOp for Load<M> {
     type Input = ();
 
     type Output = M;
@@ -44,12 +46,52 @@ impl GraphemeImmediate for String {
         self.clone()
     }
 }
-impl GraphemeImmediate for u64 {
+/// `t` or `1` is true, `f` or `0` is false.
+impl GraphemeImmediate for bool {
     fn parse(imm: String) -> anyhow::Result<Self> {
-        imm.parse().context("grapheme parse error")
+        match imm.as_str() {
+            "t" | "1" => Ok(true),
+            "f" | "0" => Ok(false),
+            _ => Err(anyhow!(
+                "cannot load {:?} as bool: expected t, f, 1 or 0",
+                imm
+            )),
+        }
     }
     fn replicate(&self) -> Self {
-        self.clone()
+        *self
+    }
+}
+macro_rules! grapheme_number {
+    ($($t:ty),*) => {
+        $(impl GraphemeImmediate for $t {
+            fn parse(imm: String) -> anyhow::Result<Self> {
+                imm.parse()
+                    .context(anyhow!("cannot load {:?} as {}", imm, types::name_of::<$t>()))
+            }
+            fn replicate(&self) -> Self {
+                *self
+            }
+        })*
+    };
+}
+grapheme_number!(u8, u64, i64, f64, usize, isize);
+
+#[cfg(test)]
+mod tests {
+    use super::GraphemeImmediate;
+
+    #[test]
+    fn parse() {
+        assert!(bool::parse("t".to_string()).unwrap());
+        assert_eq!(u8::parse("7".to_string()).unwrap(), 7);
+        assert_eq!(f64::parse("3".to_string()).unwrap(), 3.0);
+        let err = i64::parse("a".to_string()).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "cannot load \"a\" as i64: invalid digit found in string"
+        );
+        assert!(bool::parse("y".to_string()).is_err());
     }
 }
 
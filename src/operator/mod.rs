@@ -1,97 +1,251 @@
-pub mod add;
+pub mod arith;
 pub mod delete;
 pub mod load;
 pub mod stdio;
 
-use std::{fmt::Debug, marker::PhantomData};
+use std::{cell::RefCell, collections::VecDeque, fmt::Debug, marker::PhantomData, task::Waker};
+
+use anyhow::anyhow;
 
 use crate::{
-    agent::Agent,
+    agent::{Agent, InputState, Step},
     deduct::{AgentPrecursor, PipeIndex},
-    pipe::{PipeReceiver, PipeSender},
+    parser::Instruction,
+    pipe::{Element, PipeReceiver, PipeSender},
+    span::Origin,
 };
 
-pub trait IOp: Debug {
-    type Input: 'static;
-    type Output: 'static;
+use arith::{Op, Overflow};
+use delete::DeletePrecursor;
+use load::Load;
+use stdio::SxPrintf;
+
+/// the precursor of `instruction`. arithmetic without an explicit overflow variant
+/// follows `overflow`.
+pub fn precursor(
+    instruction: &Instruction,
+    overflow: Overflow,
+) -> anyhow::Result<Box<dyn AgentPrecursor<String>>> {
+    if instruction.is_partial() {
+        return Err(anyhow!("partial tuple of {} fields", instruction.arity));
+    }
+    let (li1, li2, op, lo) = (
+        &*instruction.l1,
+        &*instruction.l2,
+        &*instruction.op,
+        &*instruction.lo,
+    );
+    Ok(match op {
+        "+" | "加" => arith::precursor(Op::Add, overflow, li1, li2, lo),
+        "-" | "减" => arith::precursor(Op::Sub, overflow, li1, li2, lo),
+        "*" | "乘" => arith::precursor(Op::Mul, overflow, li1, li2, lo),
+        "/" | "除" => arith::precursor(Op::Div, overflow, li1, li2, lo),
+        "%" | "余" => arith::precursor(Op::Rem, overflow, li1, li2, lo),
+        // circled: wrapping, squared: saturating, in a triangle: checked
+        "⊕" => arith::precursor(Op::Add, Overflow::Wrapping, li1, li2, lo),
+        "⊖" => arith::precursor(Op::Sub, Overflow::Wrapping, li1, li2, lo),
+        "⊗" => arith::precursor(Op::Mul, Overflow::Wrapping, li1, li2, lo),
+        "⊞" => arith::precursor(Op::Add, Overflow::Saturating, li1, li2, lo),
+        "⊟" => arith::precursor(Op::Sub, Overflow::Saturating, li1, li2, lo),
+        "⊠" => arith::precursor(Op::Mul, Overflow::Saturating, li1, li2, lo),
+        "⨹" => arith::precursor(Op::Add, Overflow::Checked, li1, li2, lo),
+        "⨺" => arith::precursor(Op::Sub, Overflow::Checked, li1, li2, lo),
+        "⨻" => arith::precursor(Op::Mul, Overflow::Checked, li1, li2, lo),
+        "b" => IPrecursor::<Load<bool>>::new(li1, li2, lo),
+        "x" => IPrecursor::<Load<u8>>::new(li1, li2, lo),
+        "u" => IPrecursor::<Load<u64>>::new(li1, li2, lo),
+        "i" | "整" => IPrecursor::<Load<i64>>::new(li1, li2, lo),
+        "f" => IPrecursor::<Load<f64>>::new(li1, li2, lo),
+        "U" => IPrecursor::<Load<usize>>::new(li1, li2, lo),
+        "I" => IPrecursor::<Load<isize>>::new(li1, li2, lo),
+        "S" | "字" => IPrecursor::<Load<String>>::new(li1, li2, lo),
+        "~" => Box::new(DeletePrecursor::new(li1, lo)),
+        "P" | "印" => IPrecursor::<SxPrintf>::new(li1, li2, lo),
+        _ => return Err(anyhow!("operator {} is not registered", op)),
+    })
+}
+
+pub trait IOp: Debug + Send {
+    type Input: Element;
+    type Output: Element;
+    /// `exec` may block on I/O.
+    const BLOCKING: bool = false;
     fn new(imm: String) -> anyhow::Result<Self>
     where
         Self: Sized;
     fn exec(&self, e: &Self::Input) -> impl IntoIterator<Item = Self::Output>;
 }
-pub trait YOp: Debug {
-    type Input1: 'static;
-    type Input2: 'static;
-    type Output: 'static;
-    fn new() -> anyhow::Result<Self>
-    where
-        Self: Sized;
-    fn exec(&self, e1: &Self::Input1, e2: &Self::Input2) -> impl IntoIterator<Item = Self::Output>;
+pub trait YOp: Debug + Send {
+    type Input1: Element;
+    type Input2: Element;
+    type Output: Element;
+    /// an error fails the agent at its instruction.
+    fn exec(
+        &self,
+        e1: &Self::Input1,
+        e2: &Self::Input2,
+    ) -> anyhow::Result<impl IntoIterator<Item = Self::Output>>;
 }
 
+/// sends the held outputs `po` has room for, oldest first. returns whether any was sent.
+fn flush<T: Element>(po: &PipeSender<T>, held: &RefCell<VecDeque<T>>) -> bool {
+    let mut held = held.borrow_mut();
+    let mut sent = false;
+    while !held.is_empty() && po.has_room() {
+        po.send(held.pop_front().unwrap());
+        sent = true;
+    }
+    sent
+}
+
+/// an agent receives before it looks for room, so one reading its own output pipe
+/// frees the room it sends into. outputs that do not fit are held, and nothing more
+/// is received until they are sent.
 pub struct IAgent<I: IOp> {
+    li: String,
+    lo: String,
     pi: PipeReceiver<I::Input>,
     op: I,
     po: PipeSender<I::Output>,
+    held: RefCell<VecDeque<I::Output>>,
 }
 
 impl<I: IOp> Debug for IAgent<I> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("IAgent")
+            .field("li", &self.li)
+            .field("lo", &self.lo)
             .field("pi", &self.pi)
             .field("op", &self.op)
             .field("po", &self.po)
+            .field("held", &self.held.borrow().len())
             .finish()
     }
 }
 
 impl<I: IOp> Agent for IAgent<I> {
-    fn step(&self) -> bool {
-        if self.pi.is_alive() {
-            if self.pi.is_recvable() {
-                let e = self.pi.recv().unwrap();
-                for eo in self.op.exec(&e).into_iter() {
-                    self.po.send(eo);
-                }
-            }
-            true
+    fn step(&self) -> anyhow::Result<Step> {
+        let flushed = flush(&self.po, &self.held);
+        if !self.held.borrow().is_empty() {
+            Ok(if flushed {
+                Step::Progressed
+            } else {
+                Step::Idle
+            })
+        } else if !self.pi.is_alive() {
+            Ok(Step::Finished)
+        } else if self.pi.is_recvable() {
+            let e = self.pi.recv().unwrap();
+            self.held.borrow_mut().extend(self.op.exec(&e));
+            flush(&self.po, &self.held);
+            Ok(Step::Progressed)
         } else {
-            false
+            Ok(if flushed {
+                Step::Progressed
+            } else {
+                Step::Idle
+            })
         }
     }
+    fn inputs(&self) -> Vec<InputState> {
+        vec![InputState {
+            pipe: self.li.clone(),
+            pending: self.pi.pending(),
+        }]
+    }
+    fn watch(&self, waker: &Waker) {
+        self.pi.watch(waker);
+        self.po.watch_room(waker);
+    }
+    fn full_outputs(&self) -> Vec<String> {
+        if self.held.borrow().is_empty() {
+            Vec::new()
+        } else {
+            vec![self.lo.clone()]
+        }
+    }
+    fn is_blocking(&self) -> bool {
+        I::BLOCKING
+    }
 }
 
 pub struct YAgent<Y: YOp> {
+    li1: String,
+    li2: String,
+    lo: String,
     pi1: PipeReceiver<Y::Input1>,
     pi2: PipeReceiver<Y::Input2>,
     op: Y,
     po: PipeSender<Y::Output>,
+    held: RefCell<VecDeque<Y::Output>>,
 }
 
 impl<Y: YOp> Debug for YAgent<Y> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("YAgent")
+            .field("li1", &self.li1)
+            .field("li2", &self.li2)
+            .field("lo", &self.lo)
             .field("pi1", &self.pi1)
             .field("pi2", &self.pi2)
             .field("op", &self.op)
             .field("po", &self.po)
+            .field("held", &self.held.borrow().len())
             .finish()
     }
 }
 
 impl<Y: YOp> Agent for YAgent<Y> {
-    fn step(&self) -> bool {
-        if self.pi1.is_alive() && self.pi2.is_alive() {
-            if self.pi1.is_recvable() && self.pi2.is_recvable() {
-                let e1 = self.pi1.recv().unwrap();
-                let e2 = self.pi2.recv().unwrap();
-                for eo in self.op.exec(&e1, &e2) {
-                    self.po.send(eo);
-                }
-            }
-            true
+    fn step(&self) -> anyhow::Result<Step> {
+        let flushed = flush(&self.po, &self.held);
+        if !self.held.borrow().is_empty() {
+            Ok(if flushed {
+                Step::Progressed
+            } else {
+                Step::Idle
+            })
+        } else if (!self.pi1.is_alive() && !self.pi2.is_recvable())
+            || (!self.pi2.is_alive() && !self.pi1.is_recvable())
+        {
+            // with one input closed, unread elements on the other are left for the
+            // stall report rather than dropped with the agent
+            Ok(Step::Finished)
+        } else if self.pi1.is_recvable() && self.pi2.is_recvable() {
+            let e1 = self.pi1.recv().unwrap();
+            let e2 = self.pi2.recv().unwrap();
+            self.held.borrow_mut().extend(self.op.exec(&e1, &e2)?);
+            flush(&self.po, &self.held);
+            Ok(Step::Progressed)
+        } else {
+            Ok(if flushed {
+                Step::Progressed
+            } else {
+                Step::Idle
+            })
+        }
+    }
+    fn inputs(&self) -> Vec<InputState> {
+        vec![
+            InputState {
+                pipe: self.li1.clone(),
+                pending: self.pi1.pending(),
+            },
+            InputState {
+                pipe: self.li2.clone(),
+                pending: self.pi2.pending(),
+            },
+        ]
+    }
+    fn watch(&self, waker: &Waker) {
+        self.pi1.watch(waker);
+        self.pi2.watch(waker);
+        self.po.watch_room(waker);
+    }
+    fn full_outputs(&self) -> Vec<String> {
+        if self.held.borrow().is_empty() {
+            Vec::new()
         } else {
-            false
+            vec![self.lo.clone()]
         }
     }
 }
@@ -114,9 +268,12 @@ impl<I: IOp> IBuildable for I {
         let pi = idx.require_receiver(&li)?;
         let po = idx.require_sender(&lo)?;
         Ok(IAgent::<Self> {
+            li,
+            lo,
             pi,
             op: Self::new(imm)?,
             po,
+            held: RefCell::new(VecDeque::new()),
         })
     }
 }
@@ -125,6 +282,7 @@ pub trait YBuildable: YOp + Sized {
         li1: String,
         li2: String,
         lo: String,
+        op: Self,
         idx: &PipeIndex<String>,
     ) -> anyhow::Result<YAgent<Self>>;
 }
@@ -134,16 +292,21 @@ impl<Y: YOp> YBuildable for Y {
         li1: String,
         li2: String,
         lo: String,
+        op: Self,
         idx: &PipeIndex<String>,
     ) -> anyhow::Result<YAgent<Self>> {
         let pi1 = idx.require_receiver(&li1)?;
         let pi2 = idx.require_receiver(&li2)?;
         let po = idx.require_sender(&lo)?;
         Ok(YAgent::<Self> {
+            li1,
+            li2,
+            lo,
             pi1,
             pi2,
-            op: Self::new()?,
+            op,
             po,
+            held: RefCell::new(VecDeque::new()),
         })
     }
 }
@@ -157,6 +320,7 @@ pub struct IPrecursor<I: IOp> {
 }
 
 impl<I: 'static + IOp> IPrecursor<I> {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(pi: &str, imm: &str, po: &str) -> Box<dyn AgentPrecursor<String>> {
         Box::new(Self {
             pi: pi.to_string(),
@@ -167,16 +331,28 @@ impl<I: 'static + IOp> IPrecursor<I> {
     }
 }
 impl<I: 'static + IOp> AgentPrecursor<String> for IPrecursor<I> {
-    fn deduct(&self, idx: &mut crate::deduct::PipeTypeIndex<String>) -> anyhow::Result<()> {
-        idx.require::<I::Input, _>(&self.pi)?;
-        idx.require::<I::Output, _>(&self.po)?;
+    fn pipes(&self) -> Vec<String> {
+        vec![self.pi.clone(), self.po.clone()]
+    }
+    fn deduct(
+        &self,
+        idx: &mut crate::deduct::PipeTypeIndex<String>,
+        origin: &Origin,
+    ) -> anyhow::Result<()> {
+        idx.require::<I::Input, _>(&self.pi, origin)?;
+        idx.require::<I::Output, _>(&self.po, origin)?;
         Ok(())
     }
 
-    fn build(self: Box<Self>, idx: &PipeIndex<String>) -> anyhow::Result<Box<dyn Agent>> {
+    fn build(
+        self: Box<Self>,
+        idx: &PipeIndex<String>,
+        _origin: &Origin,
+    ) -> anyhow::Result<Box<dyn Agent>> {
         Ok(Box::new(I::build(self.pi, self.imm, self.po, idx)?))
     }
 }
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct YPrecursor<Y: YOp> {
     pi1: String,
@@ -184,7 +360,9 @@ pub struct YPrecursor<Y: YOp> {
     po: String,
     ph: PhantomData<Y>,
 }
-impl<Y: 'static + YOp> YPrecursor<Y> {
+#[allow(dead_code)]
+impl<Y: 'static + YOp + Default> YPrecursor<Y> {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(pi1: &str, pi2: &str, po: &str) -> Box<dyn AgentPrecursor<String>> {
         Box::new(Self {
             pi1: pi1.to_string(),
@@ -194,16 +372,33 @@ impl<Y: 'static + YOp> YPrecursor<Y> {
         })
     }
 }
-impl<Y: 'static + YOp> AgentPrecursor<String> for YPrecursor<Y> {
-    fn deduct(&self, idx: &mut crate::deduct::PipeTypeIndex<String>) -> anyhow::Result<()> {
-        idx.require::<Y::Input1, _>(&self.pi1)?;
-        idx.require::<Y::Input2, _>(&self.pi2)?;
-        idx.require::<Y::Output, _>(&self.po)?;
+impl<Y: 'static + YOp + Default> AgentPrecursor<String> for YPrecursor<Y> {
+    fn pipes(&self) -> Vec<String> {
+        vec![self.pi1.clone(), self.pi2.clone(), self.po.clone()]
+    }
+    fn deduct(
+        &self,
+        idx: &mut crate::deduct::PipeTypeIndex<String>,
+        origin: &Origin,
+    ) -> anyhow::Result<()> {
+        idx.require::<Y::Input1, _>(&self.pi1, origin)?;
+        idx.require::<Y::Input2, _>(&self.pi2, origin)?;
+        idx.require::<Y::Output, _>(&self.po, origin)?;
         Ok(())
     }
 
-    fn build(self: Box<Self>, idx: &PipeIndex<String>) -> anyhow::Result<Box<dyn Agent>> {
-        Ok(Box::new(Y::build(self.pi1, self.pi2, self.po, idx)?))
+    fn build(
+        self: Box<Self>,
+        idx: &PipeIndex<String>,
+        _origin: &Origin,
+    ) -> anyhow::Result<Box<dyn Agent>> {
+        Ok(Box::new(Y::build(
+            self.pi1,
+            self.pi2,
+            self.po,
+            Y::default(),
+            idx,
+        )?))
     }
 }
 
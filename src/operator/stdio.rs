@@ -7,6 +7,8 @@ impl IOp for SxPrintf {
 
     type Output = String;
 
+    const BLOCKING: bool = true;
+
     fn new(_: String) -> anyhow::Result<Self>
     where
         Self: Sized,
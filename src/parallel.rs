@@ -0,0 +1,484 @@
+use std::{
+    cell::Cell,
+    collections::VecDeque,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex, Weak,
+    },
+    task::{Wake, Waker},
+    thread,
+};
+
+use crate::{
+    agent::{panicked, Step},
+    controller::{Execute, Slot, Stalled, Stats, StepLimitExceeded},
+};
+
+// life cycle of an agent. `pending` counts the agents in QUEUED, RUNNING or NOTIFIED.
+const IDLE: u8 = 0;
+const QUEUED: u8 = 1;
+const RUNNING: u8 = 2;
+/// woken while running: queue again instead of going idle.
+const NOTIFIED: u8 = 3;
+const DONE: u8 = 4;
+
+thread_local! {
+    /// the pool and worker index the current thread belongs to, if any.
+    static WORKER: Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+}
+
+struct AgentCell {
+    state: AtomicU8,
+    slot: Mutex<Option<Slot>>,
+}
+
+struct Shared {
+    agents: Vec<AgentCell>,
+    /// one deque per worker: the owner pops from the back, thieves from the front.
+    locals: Vec<Mutex<VecDeque<usize>>>,
+    /// agents woken from outside the workers.
+    injector: Mutex<VecDeque<usize>>,
+    pending: AtomicUsize,
+    /// bumped whenever a sleeping worker may have something to do: an agent was
+    /// queued, nothing is pending anymore, or the run aborts.
+    epoch: AtomicU64,
+    sleepers: AtomicUsize,
+    sleep: Mutex<()>,
+    wakeup: Condvar,
+    alive: AtomicUsize,
+    /// every call of `step`, counted against `max_steps`.
+    steps: AtomicUsize,
+    fired: AtomicUsize,
+    idled: AtomicUsize,
+    max_steps: Option<usize>,
+    abort: AtomicBool,
+    error: Mutex<Option<anyhow::Error>>,
+    retired: Mutex<Stats>,
+}
+
+impl Shared {
+    fn id(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    fn wake(&self, id: usize) {
+        let state = &self.agents[id].state;
+        let mut current = state.load(Ordering::Acquire);
+        loop {
+            let next = match current {
+                IDLE => QUEUED,
+                RUNNING => NOTIFIED,
+                _ => return,
+            };
+            match state.compare_exchange(current, next, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => break,
+                Err(actual) => current = actual,
+            }
+        }
+        if current == IDLE {
+            self.pending.fetch_add(1, Ordering::AcqRel);
+            self.push(id);
+        }
+    }
+
+    fn push(&self, id: usize) {
+        match WORKER.get() {
+            Some((pool, i)) if pool == self.id() => self.locals[i].lock().unwrap().push_back(id),
+            _ => self.injector.lock().unwrap().push_back(id),
+        }
+        self.notify(false);
+    }
+
+    fn notify(&self, all: bool) {
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+        // a worker counts itself as a sleeper before it checks the epoch
+        if self.sleepers.load(Ordering::SeqCst) != 0 {
+            let _guard = self.sleep.lock().unwrap();
+            if all {
+                self.wakeup.notify_all();
+            } else {
+                self.wakeup.notify_one();
+            }
+        }
+    }
+
+    /// parks the worker until the epoch moved past `seen`.
+    fn sleep(&self, seen: u64) {
+        let mut guard = self.sleep.lock().unwrap();
+        self.sleepers.fetch_add(1, Ordering::SeqCst);
+        while self.epoch.load(Ordering::SeqCst) == seen {
+            guard = self.wakeup.wait(guard).unwrap();
+        }
+        self.sleepers.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    fn abort(&self) {
+        self.abort.store(true, Ordering::Release);
+        self.notify(true);
+    }
+
+    /// an agent stopped counting as pending.
+    fn settled(&self) {
+        if self.pending.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.notify(true);
+        }
+    }
+
+    fn find(&self, i: usize) -> Option<usize> {
+        if let Some(id) = self.locals[i].lock().unwrap().pop_back() {
+            return Some(id);
+        }
+        if let Some(id) = self.injector.lock().unwrap().pop_front() {
+            return Some(id);
+        }
+        let n = self.locals.len();
+        (1..n).find_map(|k| self.locals[(i + k) % n].lock().unwrap().pop_front())
+    }
+
+    fn fail(&self, e: anyhow::Error) {
+        self.error.lock().unwrap().get_or_insert(e);
+        self.abort();
+    }
+
+    fn work(&self, i: usize) {
+        WORKER.set(Some((self.id(), i)));
+        while !self.abort.load(Ordering::Acquire) {
+            let seen = self.epoch.load(Ordering::SeqCst);
+            match self.find(i) {
+                Some(id) => self.run_one(id),
+                None if self.pending.load(Ordering::Acquire) == 0 => break,
+                None => self.sleep(seen),
+            }
+        }
+        WORKER.set(None);
+    }
+
+    fn run_one(&self, id: usize) {
+        let cell = &self.agents[id];
+        cell.state.store(RUNNING, Ordering::Release);
+        let steps = self.steps.fetch_add(1, Ordering::AcqRel);
+        if self.max_steps.is_some_and(|max_steps| steps >= max_steps) {
+            self.steps.fetch_sub(1, Ordering::AcqRel);
+            cell.state.store(QUEUED, Ordering::Release);
+            self.abort();
+            return;
+        }
+
+        let mut guard = cell.slot.lock().unwrap();
+        let slot = guard.as_mut().unwrap();
+        // a panic must not take the worker down before the agent is settled
+        let step = panic::catch_unwind(AssertUnwindSafe(|| slot.agent.step()))
+            .unwrap_or_else(|payload| Err(panicked(payload)));
+        match step {
+            Ok(Step::Progressed) => {
+                slot.stats.progressed();
+                self.fired.fetch_add(1, Ordering::Relaxed);
+                cell.state.store(QUEUED, Ordering::Release);
+                self.push(id);
+            }
+            Ok(Step::Idle) => {
+                slot.stats.idled();
+                self.idled.fetch_add(1, Ordering::Relaxed);
+                if cell
+                    .state
+                    .compare_exchange(RUNNING, IDLE, Ordering::AcqRel, Ordering::Acquire)
+                    .is_ok()
+                {
+                    self.settled();
+                } else {
+                    cell.state.store(QUEUED, Ordering::Release);
+                    self.push(id);
+                }
+            }
+            Ok(Step::Finished) => {
+                cell.state.store(DONE, Ordering::Release);
+                let slot = guard.take().unwrap();
+                drop(guard);
+                self.retired
+                    .lock()
+                    .unwrap()
+                    .agents
+                    .push((slot.origin.clone(), slot.stats.clone()));
+                // dropping the agent closes its outlets and wakes their readers,
+                // so it must happen while this agent still counts as pending.
+                drop(slot);
+                self.alive.fetch_sub(1, Ordering::AcqRel);
+                self.settled();
+            }
+            Err(e) => {
+                let e = slot.fail(e);
+                drop(guard);
+                self.fail(e);
+            }
+        }
+    }
+}
+
+struct AgentWaker {
+    id: usize,
+    shared: Weak<Shared>,
+}
+impl Wake for AgentWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+    fn wake_by_ref(self: &Arc<Self>) {
+        if let Some(shared) = self.shared.upgrade() {
+            shared.wake(self.id);
+        }
+    }
+}
+
+/// work-stealing executor running agents on several OS threads.
+/// each step of an agent counts as a step toward `max_steps`. a worker that finds
+/// nothing to run sleeps until an agent is queued.
+pub struct ParallelExecutor {
+    threads: usize,
+    agents: Vec<Slot>,
+    shared: Option<Arc<Shared>>,
+}
+
+impl ParallelExecutor {
+    /// `threads` must be at least 1.
+    pub fn new(agents: Vec<Slot>, threads: usize) -> Self {
+        assert!(threads > 0, "no worker thread");
+        Self {
+            threads,
+            agents,
+            shared: None,
+        }
+    }
+
+    fn live<'a>(
+        guards: &'a [std::sync::MutexGuard<'a, Option<Slot>>],
+    ) -> impl Iterator<Item = &'a Slot> {
+        guards.iter().filter_map(|g| g.as_ref())
+    }
+}
+
+impl Execute for ParallelExecutor {
+    fn run(&mut self, max_steps: Option<usize>) -> anyhow::Result<()> {
+        let agents = std::mem::take(&mut self.agents);
+        let n = agents.len();
+        let shared = Arc::new_cyclic(|weak: &Weak<Shared>| {
+            let agents = agents
+                .into_iter()
+                .enumerate()
+                .map(|(id, slot)| {
+                    slot.agent.watch(&Waker::from(Arc::new(AgentWaker {
+                        id,
+                        shared: weak.clone(),
+                    })));
+                    AgentCell {
+                        state: AtomicU8::new(QUEUED),
+                        slot: Mutex::new(Some(slot)),
+                    }
+                })
+                .collect();
+            Shared {
+                agents,
+                locals: (0..self.threads).map(|_| Mutex::default()).collect(),
+                injector: Mutex::new((0..n).collect()),
+                pending: AtomicUsize::new(n),
+                epoch: AtomicU64::new(0),
+                sleepers: AtomicUsize::new(0),
+                sleep: Mutex::new(()),
+                wakeup: Condvar::new(),
+                alive: AtomicUsize::new(n),
+                steps: AtomicUsize::new(0),
+                fired: AtomicUsize::new(0),
+                idled: AtomicUsize::new(0),
+                max_steps,
+                abort: AtomicBool::new(false),
+                error: Mutex::new(None),
+                retired: Mutex::default(),
+            }
+        });
+        self.shared = Some(shared.clone());
+
+        thread::scope(|s| {
+            for i in 0..self.threads {
+                let shared = &shared;
+                s.spawn(move || shared.work(i));
+            }
+        });
+
+        if let Some(e) = shared.error.lock().unwrap().take() {
+            return Err(e);
+        }
+        let steps = shared.steps.load(Ordering::Acquire);
+        let guards: Vec<_> = shared
+            .agents
+            .iter()
+            .map(|c| c.slot.lock().unwrap())
+            .collect();
+        if shared.abort.load(Ordering::Acquire) {
+            return Err(StepLimitExceeded::new(steps, Self::live(&guards)).into());
+        }
+        if shared.alive.load(Ordering::Acquire) == 0 {
+            Ok(())
+        } else {
+            Err(Stalled::new(steps, Self::live(&guards)).into())
+        }
+    }
+
+    fn stats(&self) -> Stats {
+        let Some(shared) = &self.shared else {
+            return Stats::default();
+        };
+        let mut stats = shared.retired.lock().unwrap().clone();
+        stats.steps = shared.steps.load(Ordering::Acquire);
+        stats.fired = shared.fired.load(Ordering::Acquire);
+        stats.idled = shared.idled.load(Ordering::Acquire);
+        for cell in shared.agents.iter() {
+            if let Some(slot) = cell.slot.lock().unwrap().as_ref() {
+                stats.agents.push((slot.origin.clone(), slot.stats.clone()));
+            }
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        task::Waker,
+    };
+
+    use crate::{
+        agent::{Agent, InputState, Step},
+        controller::{Execute, Slot, Stalled},
+        pipe::{PipeBuilder, PipeReceiver, PipeSender},
+    };
+
+    use super::ParallelExecutor;
+
+    #[derive(Debug)]
+    struct Producer(RefCell<u64>, PipeSender<u64>);
+    impl Agent for Producer {
+        fn step(&self) -> anyhow::Result<Step> {
+            let mut n = self.0.borrow_mut();
+            if *n == 0 {
+                return Ok(Step::Finished);
+            }
+            self.1.send(*n);
+            *n -= 1;
+            Ok(Step::Progressed)
+        }
+        fn inputs(&self) -> Vec<InputState> {
+            Vec::new()
+        }
+        fn watch(&self, _waker: &Waker) {}
+    }
+
+    #[derive(Debug)]
+    struct Summer(PipeReceiver<u64>, Arc<AtomicU64>);
+    impl Agent for Summer {
+        fn step(&self) -> anyhow::Result<Step> {
+            if !self.0.is_alive() {
+                return Ok(Step::Finished);
+            }
+            match self.0.recv() {
+                Some(e) => {
+                    self.1.fetch_add(*e, Ordering::SeqCst);
+                    Ok(Step::Progressed)
+                }
+                None => Ok(Step::Idle),
+            }
+        }
+        fn inputs(&self) -> Vec<InputState> {
+            vec![InputState {
+                pipe: "A".to_string(),
+                pending: self.0.pending(),
+            }]
+        }
+        fn watch(&self, waker: &Waker) {
+            self.0.watch(waker);
+        }
+    }
+
+    /// sends once and panics, with a receiver waiting on it.
+    #[derive(Debug)]
+    struct Panicker(PipeSender<u64>);
+    impl Agent for Panicker {
+        fn step(&self) -> anyhow::Result<Step> {
+            self.0.send(1);
+            panic!("boom");
+        }
+        fn inputs(&self) -> Vec<InputState> {
+            Vec::new()
+        }
+        fn watch(&self, _waker: &Waker) {}
+    }
+
+    #[test]
+    fn fan_out() -> anyhow::Result<()> {
+        let b = PipeBuilder::new::<u64>();
+        let sum = Arc::new(AtomicU64::new(0));
+        let mut agents = vec![Slot::new(
+            "producer".to_string(),
+            Box::new(Producer(RefCell::new(1000), b.require_sender()?)),
+        )];
+        for _ in 0..8 {
+            agents.push(Slot::new(
+                "summer".to_string(),
+                Box::new(Summer(b.require_receiver()?, sum.clone())),
+            ));
+        }
+        drop(b);
+
+        let mut e = ParallelExecutor::new(agents, 4);
+        e.run(None)?;
+        assert_eq!(sum.load(Ordering::SeqCst), 8 * 1000 * 1001 / 2);
+        let stats = e.stats();
+        assert_eq!(stats.fired, 1000 + 8 * 1000);
+        assert_eq!(stats.steps, stats.fired + stats.idled + 9);
+        Ok(())
+    }
+
+    #[test]
+    fn stalled() -> anyhow::Result<()> {
+        let b = PipeBuilder::new::<u64>();
+        let sum = Arc::new(AtomicU64::new(0));
+        // the sender is kept alive by the test, but nothing is ever sent
+        let s = b.require_sender::<u64>()?;
+        let agents = vec![Slot::new(
+            "summer".to_string(),
+            Box::new(Summer(b.require_receiver()?, sum.clone())),
+        )];
+        drop(b);
+
+        let mut e = ParallelExecutor::new(agents, 2);
+        let err = e.run(None).unwrap_err().downcast::<Stalled>().unwrap();
+        assert_eq!(err.blocked[0].waiting[0].pipe, "A");
+        drop(s);
+        Ok(())
+    }
+
+    #[test]
+    fn panic() -> anyhow::Result<()> {
+        let b = PipeBuilder::new::<u64>();
+        let agents = vec![
+            Slot::new(
+                "summer".to_string(),
+                Box::new(Summer(b.require_receiver()?, Arc::default())),
+            ),
+            Slot::new(
+                "panicker".to_string(),
+                Box::new(Panicker(b.require_sender()?)),
+            ),
+        ];
+        drop(b);
+
+        let mut e = ParallelExecutor::new(agents, 2);
+        let err = e.run(None).unwrap_err();
+        assert_eq!(format!("{:#}", err), "panicker failed: panicked: boom");
+        Ok(())
+    }
+}
@@ -0,0 +1,385 @@
+use std::{path::Path, sync::Arc};
+
+use anyhow::anyhow;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    diagnostic::Diagnostics,
+    span::{is_line_break, Origin, Span},
+};
+
+/// a tuple of the script: `l1`, `l2`, `op` and `lo` are its fields, with brackets
+/// and escape sequences resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub l1: String,
+    pub l2: String,
+    pub op: String,
+    pub lo: String,
+    /// fields of the tuple: 4, unless it is a trailing partial tuple, whose missing
+    /// fields are left empty.
+    pub arity: usize,
+    pub span: Span,
+}
+impl Instruction {
+    pub fn is_partial(&self) -> bool {
+        self.arity < 4
+    }
+}
+
+/// parses every instruction of `source`, a trailing partial tuple included.
+pub fn parse(
+    source: &str,
+    syntax: Syntax,
+    diagnostics: &mut Diagnostics,
+) -> anyhow::Result<Vec<Instruction>> {
+    let gv: Vec<&str> = source.graphemes(true).collect();
+    let fields = fields(&gv, syntax, diagnostics)?;
+    let layout = Layout::new(&gv);
+    let mut instructions = Vec::with_capacity(fields.len() / 4);
+    for (index, tuple) in fields.chunks(4).enumerate() {
+        let last = &tuple[tuple.len() - 1];
+        let span = layout.span(index, tuple[0].offset, last.offset + last.len);
+        let value = |k: usize| tuple.get(k).map_or_else(String::new, |f| f.value.clone());
+        instructions.push(Instruction {
+            l1: value(0),
+            l2: value(1),
+            op: value(2),
+            lo: value(3),
+            arity: tuple.len(),
+            span,
+        });
+    }
+    Ok(instructions)
+}
+
+/// one field of an instruction: a single grapheme, an escape sequence, or a literal of any
+/// number of graphemes between `[` and `]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Field {
+    /// the grapheme, or the graphemes between the brackets, with escape sequences replaced.
+    value: String,
+    /// graphemes before the field.
+    offset: usize,
+    /// graphemes of the field, brackets included.
+    len: usize,
+}
+
+/// the escape sequence at the start of `gv`, which starts with `\`, and its length in graphemes.
+fn escape(gv: &[&str]) -> Result<(char, usize), &'static str> {
+    let c = match gv.get(1).copied() {
+        Some("n") => '\n',
+        Some("t") => '\t',
+        Some("s") => ' ',
+        Some("\\") => '\\',
+        Some("[") => '[',
+        Some("]") => ']',
+        Some("u") => {
+            if gv.get(2) != Some(&"{") {
+                return Err("expected { after \\u");
+            }
+            // at most 6 hex digits, as in rust
+            let close = gv
+                .iter()
+                .skip(3)
+                .take(7)
+                .position(|g| *g == "}")
+                .map(|p| p + 3)
+                .ok_or("unclosed \\u{...} escape")?;
+            let digits = gv[3..close].concat();
+            if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err("invalid code point in \\u{...} escape");
+            }
+            return u32::from_str_radix(&digits, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .map(|c| (c, close + 1))
+                .ok_or("invalid code point in \\u{...} escape");
+        }
+        Some(_) => return Err("unknown escape sequence"),
+        None => return Err("unfinished escape sequence"),
+    };
+    Ok((c, 2))
+}
+
+/// `message` located at the instruction the field at `offset` is part of, up to `end`.
+fn field_error(
+    gv: &[&str],
+    fields: &[Field],
+    offset: usize,
+    end: usize,
+    message: &str,
+) -> anyhow::Error {
+    let index = fields.len() / 4;
+    let start = fields.get(4 * index).map_or(offset, |f| f.offset);
+    let span = Layout::new(gv).span(index, start, end.min(gv.len()));
+    Origin::Instruction(span).locate(anyhow!("{}", message))
+}
+
+/// first line of a script in the annotated syntax, if its extension is not `.ppop-ill`.
+const PRAGMA: &str = "#!ppop-ill";
+
+/// how the graphemes of a script make instructions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    /// every grapheme is part of an instruction.
+    Aligned,
+    /// line breaks and indentation between instructions are skipped, and a space or tab
+    /// after an instruction starts a comment running to the end of the line.
+    Annotated,
+}
+impl Syntax {
+    /// the annotated syntax for `.ppop-ill` scripts and scripts starting with the pragma line.
+    pub fn of(script: &Path, source: &str) -> Self {
+        let pragma = source.lines().next() == Some(PRAGMA);
+        if script.extension().is_some_and(|ext| ext == "ppop-ill") || pragma {
+            Syntax::Annotated
+        } else {
+            Syntax::Aligned
+        }
+    }
+}
+
+fn first_line(gv: &[&str]) -> String {
+    gv.iter()
+        .take_while(|g| !is_line_break(g))
+        .copied()
+        .collect()
+}
+
+/// offset of the line break ending the line `offset` is on, or the end of the script.
+fn end_of_line(gv: &[&str], offset: usize) -> usize {
+    gv[offset..]
+        .iter()
+        .position(|g| is_line_break(g))
+        .map_or(gv.len(), |p| offset + p)
+}
+
+/// splits a script split into graphemes into fields. an unclosed `[` or a malformed escape
+/// sequence is reported to `diagnostics`, and stands for itself.
+fn fields(
+    gv: &[&str],
+    syntax: Syntax,
+    diagnostics: &mut Diagnostics,
+) -> anyhow::Result<Vec<Field>> {
+    let mut fields = Vec::new();
+    let mut offset = 0;
+    if syntax == Syntax::Annotated && first_line(gv) == PRAGMA {
+        offset = end_of_line(gv, 0);
+    }
+    while offset < gv.len() {
+        if syntax == Syntax::Annotated && fields.len() % 4 == 0 {
+            match gv[offset] {
+                g if is_line_break(g) => {
+                    offset += 1;
+                    continue;
+                }
+                " " | "\t" => {
+                    // after a tuple on the same line a comment, otherwise indentation
+                    let after_tuple = fields.last().is_some_and(|f: &Field| {
+                        !gv[f.offset + f.len..offset]
+                            .iter()
+                            .any(|g| is_line_break(g))
+                    });
+                    offset = if after_tuple {
+                        end_of_line(gv, offset)
+                    } else {
+                        offset + 1
+                    };
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        let mut literal = gv[offset] == "[";
+        let mut value = String::new();
+        let mut i = offset + usize::from(literal);
+        loop {
+            match gv.get(i).copied() {
+                None if literal => {
+                    let e = field_error(gv, &fields, offset, gv.len(), "unclosed literal");
+                    diagnostics.invalid(e)?;
+                    (literal, value, i) = (false, "[".to_string(), offset + 1);
+                }
+                Some("]") if literal => {
+                    i += 1;
+                    break;
+                }
+                Some("\\") => match escape(&gv[i..]) {
+                    Ok((c, len)) => {
+                        value.push(c);
+                        i += len;
+                    }
+                    Err(m) => {
+                        diagnostics.invalid(field_error(gv, &fields, offset, i + 2, m))?;
+                        value.push('\\');
+                        i += 1;
+                    }
+                },
+                Some(g) => {
+                    value.push_str(g);
+                    i += 1;
+                }
+                None => unreachable!(),
+            }
+            if !literal {
+                break;
+            }
+        }
+        fields.push(Field {
+            value,
+            offset,
+            len: i - offset,
+        });
+        offset = i;
+    }
+    Ok(fields)
+}
+
+/// lines and positions of the graphemes of a script.
+struct Layout<'a> {
+    gv: &'a [&'a str],
+    lines: Vec<Arc<str>>,
+    /// (line, column) of each grapheme, both 0-based
+    positions: Vec<(usize, usize)>,
+}
+impl<'a> Layout<'a> {
+    fn new(gv: &'a [&'a str]) -> Self {
+        let mut lines: Vec<Arc<str>> = Vec::new();
+        let mut positions = Vec::with_capacity(gv.len());
+        let mut current = String::new();
+        let mut column = 0;
+        for g in gv {
+            positions.push((lines.len(), column));
+            if is_line_break(g) {
+                lines.push(std::mem::take(&mut current).into());
+                column = 0;
+            } else {
+                current.push_str(g);
+                column += 1;
+            }
+        }
+        lines.push(current.into());
+        Self {
+            gv,
+            lines,
+            positions,
+        }
+    }
+
+    /// the span of instruction `index`, made of the graphemes `offset..end`.
+    fn span(&self, index: usize, offset: usize, end: usize) -> Span {
+        let (line, column) = self.positions[offset];
+        Span {
+            index,
+            line: line + 1,
+            column: column + 1,
+            offset,
+            text: self.gv[offset..end].concat(),
+            source: self.lines[line].clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        diagnostic::{Diagnostics, Invalid},
+        span::render,
+    };
+
+    use super::{parse, Instruction, Syntax};
+
+    fn strict() -> Diagnostics {
+        Diagnostics::new(Invalid::Error)
+    }
+
+    fn fields(instructions: &[Instruction]) -> Vec<&str> {
+        instructions
+            .iter()
+            .flat_map(|i| [&i.l1, &i.l2, &i.op, &i.lo])
+            .map(String::as_str)
+            .collect()
+    }
+
+    #[test]
+    fn literal() {
+        let instructions = parse("#0~!![42]uA!AaP", Syntax::Aligned, &mut strict()).unwrap();
+        assert_eq!(
+            fields(&instructions),
+            ["#", "0", "~", "!", "!", "42", "u", "A", "!", "A", "a", "P"]
+        );
+        assert_eq!(instructions[1].span.text, "![42]uA");
+        let span = &instructions[2].span;
+        assert_eq!((span.column, span.offset), (12, 11));
+
+        let e = parse("#0~!![42uA", Syntax::Aligned, &mut strict()).unwrap_err();
+        assert_eq!(
+            render(&e, Path::new("a.ppop")).unwrap(),
+            "unclosed literal\n --> a.ppop:1:5\n  |\n1 | #0~!![42uA\n  |     ^^^^^^"
+        );
+
+        // outside of strict mode, the bracket stands for itself
+        let mut diagnostics = Diagnostics::new(Invalid::Warn);
+        let instructions = parse("#0~!![42uAB!", Syntax::Aligned, &mut diagnostics).unwrap();
+        assert_eq!(
+            (instructions[1].l2.as_str(), instructions[1].op.as_str()),
+            ("[", "4")
+        );
+        assert_eq!(diagnostics.warnings().len(), 1);
+    }
+
+    #[test]
+    fn escape() {
+        let instructions = parse(r"\n[a\]\u{1F600}]\s\\", Syntax::Aligned, &mut strict()).unwrap();
+        assert_eq!(fields(&instructions), ["\n", "a]\u{1F600}", " ", "\\"]);
+
+        let e = parse(r"#0~!!\qSA", Syntax::Aligned, &mut strict()).unwrap_err();
+        assert_eq!(
+            render(&e, Path::new("a.ppop")).unwrap(),
+            "unknown escape sequence\n --> a.ppop:1:5\n  |\n1 | #0~!!\\qSA\n  |     ^^^"
+        );
+
+        // the closing brace is looked for among the next 7 graphemes only
+        let e = parse(r"!\u{1234567}SA!}SB", Syntax::Aligned, &mut strict()).unwrap_err();
+        assert_eq!(e.root_cause().to_string(), "unclosed \\u{...} escape");
+        let e = parse(r"!\u{+41}SA", Syntax::Aligned, &mut strict()).unwrap_err();
+        assert_eq!(
+            e.root_cause().to_string(),
+            "invalid code point in \\u{...} escape"
+        );
+    }
+
+    #[test]
+    fn annotated() {
+        let source = "#!ppop-ill\n#0~!  #: usize\n\n!HSA!WSB\tH, W\n  \tABaP print";
+        assert_eq!(Syntax::of(Path::new("a.ppop"), source), Syntax::Annotated);
+        let instructions = parse(source, Syntax::Annotated, &mut strict()).unwrap();
+        assert_eq!(fields(&instructions).concat(), "#0~!!HSA!WSBABaP");
+        let spans: Vec<_> = instructions.iter().map(|i| &i.span).collect();
+        assert_eq!((spans[0].line, spans[0].column), (2, 1));
+        assert_eq!((spans[2].line, spans[2].column), (4, 5));
+        // indented, not commented out
+        assert_eq!(spans[3].text, "ABaP");
+        assert_eq!((spans[3].line, spans[3].column), (5, 4));
+
+        assert_eq!(
+            Syntax::of(Path::new("a.ppop-ill"), "#0~! x"),
+            Syntax::Annotated
+        );
+        assert_eq!(Syntax::of(Path::new("a.ppop"), "#0~! x"), Syntax::Aligned);
+    }
+
+    #[test]
+    fn partial() {
+        let instructions = parse("#0~!!H", Syntax::Aligned, &mut strict()).unwrap();
+        assert_eq!(instructions.len(), 2);
+        assert!(!instructions[0].is_partial());
+        let partial = &instructions[1];
+        assert!(partial.is_partial());
+        assert_eq!((partial.l2.as_str(), partial.op.as_str()), ("H", ""));
+        assert_eq!(partial.span.text, "!H");
+    }
+}
@@ -1,31 +1,106 @@
 use std::{
-    cell::{OnceCell, Ref, RefCell},
+    collections::VecDeque,
     fmt::Debug,
-    rc::{Rc, Weak},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock, Weak,
+    },
+    task::Waker,
 };
 
 struct PipeEntry<T> {
-    buf: Rc<PipeBuf<T>>,
+    buf: Arc<PipeBuf<T>>,
 }
 impl<T> Debug for PipeEntry<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("PipeEntry").field("buf", &self.buf).finish()
     }
 }
+impl<T> Drop for PipeEntry<T> {
+    // the last inlet is gone: readers must notice the pipe is closing.
+    fn drop(&mut self) {
+        self.buf.wake_all();
+    }
+}
 
 struct PipeBuf<T> {
-    entry: OnceCell<Weak<PipeEntry<T>>>,
-    queue: RefCell<Vec<T>>,
+    entry: OnceLock<Weak<PipeEntry<T>>>,
+    queue: Mutex<Queue<T>>,
+    /// the most elements buffered before the pipe counts as full. `None` is unbounded.
+    capacity: Option<usize>,
+    wakers: Mutex<Vec<Waker>>,
+    /// producers waiting for a full pipe to drain.
+    room_wakers: Mutex<Vec<Waker>>,
+    usage: Arc<Usage>,
+}
+impl<T> Drop for PipeBuf<T> {
+    fn drop(&mut self) {
+        let n = self.queue.get_mut().unwrap().items.len();
+        self.usage.buffered.fetch_sub(n, Ordering::Relaxed);
+    }
+}
+
+/// element counts shared by every pipe of a pipeline.
+#[derive(Debug, Default)]
+pub struct Usage {
+    buffered: AtomicUsize,
+    produced: AtomicUsize,
+}
+impl Usage {
+    /// elements currently held in memory, summed over every pipe.
+    pub fn buffered(&self) -> usize {
+        self.buffered.load(Ordering::Relaxed)
+    }
+    /// elements ever sent.
+    pub fn produced(&self) -> usize {
+        self.produced.load(Ordering::Relaxed)
+    }
+}
+
+/// how a pipe is set up.
+#[derive(Debug, Clone, Default)]
+pub struct PipeConfig {
+    /// the most elements buffered before the pipe counts as full. `None` is unbounded.
+    pub capacity: Option<usize>,
+    pub usage: Arc<Usage>,
 }
 impl<T> Debug for PipeBuf<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let queue = self.queue.lock().unwrap();
         f.debug_struct("PipeBuf")
             .field("entry", &self.entry)
-            .field("queue", &format!("[={}=]", self.queue.borrow().len()))
+            .field("queue", &format!("{}[={}=]", queue.base, queue.items.len()))
+            .field("capacity", &self.capacity)
+            .field("wakers", &self.wakers.lock().unwrap().len())
+            .field("room_wakers", &self.room_wakers.lock().unwrap().len())
+            .field("usage", &self.usage)
             .finish()
     }
 }
 
+/// the elements not yet read by every outlet. positions are absolute:
+/// `items[0]` is the `base`-th element ever sent.
+struct Queue<T> {
+    items: VecDeque<T>,
+    base: usize,
+    /// read position of each outlet, indexed by outlet id. `None` once dropped.
+    cursors: Vec<Option<usize>>,
+}
+impl<T> Queue<T> {
+    fn end(&self) -> usize {
+        self.base + self.items.len()
+    }
+    /// drops the elements every live cursor has passed. returns how many were dropped.
+    fn compact(&mut self) -> usize {
+        let min = self.cursors.iter().flatten().min().copied();
+        let min = min.unwrap_or(self.end());
+        let n = min - self.base;
+        self.items.drain(..n);
+        self.base = min;
+        n
+    }
+}
+
 impl<T> PipeEntry<T> {
     fn pass(&self, e: T) {
         self.buf.push(e);
@@ -34,19 +109,98 @@ impl<T> PipeEntry<T> {
 
 impl<T> PipeBuf<T> {
     fn push(&self, e: T) {
-        self.queue.borrow_mut().push(e);
+        self.usage.produced.fetch_add(1, Ordering::Relaxed);
+        let mut queue = self.queue.lock().unwrap();
+        // nobody can read it anymore
+        if queue.cursors.iter().any(Option::is_some) {
+            queue.items.push_back(e);
+            self.usage.buffered.fetch_add(1, Ordering::Relaxed);
+        } else {
+            queue.base += 1;
+        }
+        drop(queue);
+        self.wake_all();
+    }
+    fn watch(&self, waker: &Waker) {
+        self.wakers.lock().unwrap().push(waker.clone());
+    }
+    fn watch_room(&self, waker: &Waker) {
+        self.room_wakers.lock().unwrap().push(waker.clone());
+    }
+    fn wake_all(&self) {
+        for waker in self.wakers.lock().unwrap().iter() {
+            waker.wake_by_ref();
+        }
+    }
+    fn wake_producers(&self) {
+        for waker in self.room_wakers.lock().unwrap().iter() {
+            waker.wake_by_ref();
+        }
+    }
+    /// accounts for the elements dropped by [`Queue::compact`] out of `before`.
+    /// returns whether the pipe was full before and is not anymore.
+    fn compacted(&self, before: usize, dropped: usize) -> bool {
+        self.usage.buffered.fetch_sub(dropped, Ordering::Relaxed);
+        self.capacity
+            .is_some_and(|c| before >= c && before - dropped < c)
+    }
+    fn has_room(&self) -> bool {
+        self.capacity
+            .is_none_or(|c| self.queue.lock().unwrap().items.len() < c)
+    }
+    /// registers a cursor at the oldest element still buffered.
+    fn open(&self) -> usize {
+        let mut queue = self.queue.lock().unwrap();
+        let base = queue.base;
+        queue.cursors.push(Some(base));
+        queue.cursors.len() - 1
+    }
+    fn close(&self, cursor: usize) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.cursors[cursor] = None;
+        let before = queue.items.len();
+        let freed = self.compacted(before, queue.compact());
+        drop(queue);
+        if freed {
+            self.wake_producers();
+        }
     }
-    fn get(&self, i: usize) -> Option<Ref<T>> {
-        let q = self.queue.borrow();
-        if i < q.len() {
-            Some(Ref::map(self.queue.borrow(), |v| &v[i]))
+    /// takes the element at `cursor` and moves past it. the last outlet to read an
+    /// element takes it, the others get a clone.
+    fn next(&self, cursor: usize) -> Option<T>
+    where
+        T: Clone,
+    {
+        let mut queue = self.queue.lock().unwrap();
+        let loc = queue.cursors[cursor].unwrap();
+        if loc == queue.end() {
+            return None;
+        }
+        queue.cursors[cursor] = Some(loc + 1);
+        let before = queue.items.len();
+        let last = queue.cursors.iter().flatten().all(|c| *c > loc);
+        let (e, freed) = if last {
+            let e = queue.items.pop_front().unwrap();
+            queue.base += 1;
+            let dropped = 1 + queue.compact();
+            (e, self.compacted(before, dropped))
         } else {
-            None
+            (queue.items[loc - queue.base].clone(), false)
+        };
+        drop(queue);
+        if freed {
+            self.wake_producers();
         }
+        Some(e)
     }
-    fn is_gettable(&self, i: usize) -> bool {
-        let q = self.queue.borrow();
-        i < q.len()
+    fn count_from(&self, cursor: usize) -> usize {
+        let queue = self.queue.lock().unwrap();
+        queue.end() - queue.cursors[cursor].unwrap()
+    }
+    /// elements currently held in memory.
+    #[cfg(test)]
+    fn buffered(&self) -> usize {
+        self.queue.lock().unwrap().items.len()
     }
     fn is_active(&self) -> bool {
         self.entry.get().unwrap().strong_count() != 0
@@ -54,7 +208,7 @@ impl<T> PipeBuf<T> {
 }
 
 pub struct PipeInlet<T> {
-    entry: Rc<PipeEntry<T>>,
+    entry: Arc<PipeEntry<T>>,
 }
 impl<T> Debug for PipeInlet<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -65,37 +219,57 @@ impl<T> Debug for PipeInlet<T> {
 }
 
 pub struct PipeOutlet<T> {
-    loc: RefCell<usize>,
-    buf: Rc<PipeBuf<T>>,
+    cursor: usize,
+    buf: Arc<PipeBuf<T>>,
 }
 impl<T> Debug for PipeOutlet<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("PipeOutlet")
-            .field("loc", &self.loc)
+            .field("cursor", &self.cursor)
             .field("buf", &self.buf)
             .finish()
     }
 }
+impl<T> Drop for PipeOutlet<T> {
+    fn drop(&mut self) {
+        self.buf.close(self.cursor);
+    }
+}
 
 impl<T> PipeInlet<T> {
+    /// never blocks, even on a full pipe: producers check [`PipeInlet::has_room`] first.
     pub fn send(&self, t: T) {
         self.entry.pass(t);
     }
+    pub fn has_room(&self) -> bool {
+        self.entry.buf.has_room()
+    }
+    /// `waker` is woken whenever the pipe stops being full.
+    pub fn watch_room(&self, waker: &Waker) {
+        self.entry.buf.watch_room(waker);
+    }
 }
 
 impl<T> PipeOutlet<T> {
-    pub fn recv(&self) -> Option<Ref<T>> {
-        let r = self.buf.get(*self.loc.borrow());
-        if r.is_some() {
-            *self.loc.borrow_mut() += 1;
-        }
-        r
+    pub fn recv(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        self.buf.next(self.cursor)
     }
     pub fn is_recvable(&self) -> bool {
-        self.buf.is_gettable(*self.loc.borrow())
+        self.pending() != 0
     }
+    /// checks the inlets before the queue: once every inlet is gone the queue is final.
     pub fn is_alive(&self) -> bool {
-        self.buf.is_active() || self.buf.is_gettable(*self.loc.borrow())
+        self.buf.is_active() || self.is_recvable()
+    }
+    pub fn pending(&self) -> usize {
+        self.buf.count_from(self.cursor)
+    }
+    /// `waker` is woken whenever an element arrives or the pipe closes.
+    pub fn watch(&self, waker: &Waker) {
+        self.buf.watch(waker);
     }
 }
 
@@ -133,20 +307,37 @@ impl<T> PipeOutlet<T> {
 //     }
 // }
 
+/// while a stem is alive, it keeps every element buffered for the outlets still to come.
 pub struct PipeStem<T> {
-    entry: Rc<PipeEntry<T>>,
-    buf: Rc<PipeBuf<T>>,
+    entry: Arc<PipeEntry<T>>,
+    outlet: PipeOutlet<T>,
 }
 
 impl<T> PipeStem<T> {
+    #[cfg(test)]
     pub fn new() -> Self {
-        let buf = Rc::new(PipeBuf {
-            entry: OnceCell::new(),
-            queue: RefCell::new(Vec::new()),
+        Self::with_config(PipeConfig::default())
+    }
+    pub fn with_config(config: PipeConfig) -> Self {
+        let buf = Arc::new(PipeBuf {
+            entry: OnceLock::new(),
+            queue: Mutex::new(Queue {
+                items: VecDeque::new(),
+                base: 0,
+                cursors: Vec::new(),
+            }),
+            capacity: config.capacity,
+            wakers: Mutex::new(Vec::new()),
+            room_wakers: Mutex::new(Vec::new()),
+            usage: config.usage,
         });
-        let entry = Rc::new(PipeEntry { buf: buf.clone() });
-        buf.entry.set(Rc::downgrade(&entry)).unwrap();
-        Self { entry, buf }
+        let entry = Arc::new(PipeEntry { buf: buf.clone() });
+        buf.entry.set(Arc::downgrade(&entry)).unwrap();
+        let outlet = PipeOutlet {
+            cursor: buf.open(),
+            buf,
+        };
+        Self { entry, outlet }
     }
     pub fn require_inlet(&self) -> PipeInlet<T> {
         PipeInlet {
@@ -154,9 +345,10 @@ impl<T> PipeStem<T> {
         }
     }
     pub fn require_outlet(&self) -> PipeOutlet<T> {
+        let buf = self.outlet.buf.clone();
         PipeOutlet {
-            loc: RefCell::new(0),
-            buf: self.buf.clone(),
+            cursor: buf.open(),
+            buf,
         }
     }
 }
@@ -202,9 +394,18 @@ impl<T> PipeStem<T> {
 
 #[cfg(test)]
 mod tests {
-    use std::{any::Any, fmt::Display, marker::PhantomData};
-
-    use crate::pipe::pipe::PipeStem;
+    use std::{
+        any::Any,
+        fmt::Display,
+        marker::PhantomData,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::{Wake, Waker},
+    };
+
+    use crate::pipe::pipe::{PipeConfig, PipeStem, Usage};
 
     #[test]
     fn test() {
@@ -233,6 +434,138 @@ mod tests {
         println!("{:?} {:?}", r, rr);
     }
 
+    struct Counter(AtomicUsize);
+    impl Wake for Counter {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn watch() {
+        let b = PipeStem::<u64>::new();
+        let s = b.require_inlet();
+        let r = b.require_outlet();
+        drop(b);
+
+        let counter = Arc::new(Counter(AtomicUsize::new(0)));
+        r.watch(&Waker::from(counter.clone()));
+
+        s.send(1);
+        s.send(2);
+        assert_eq!(counter.0.load(Ordering::SeqCst), 2);
+        drop(s);
+        assert_eq!(counter.0.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn gc() {
+        let b = PipeStem::<u64>::new();
+        let s = b.require_inlet();
+        let r = b.require_outlet();
+        let rr = b.require_outlet();
+        s.send(1);
+        s.send(2);
+        // the stem still holds them for outlets to come
+        drop(b);
+        assert_eq!(r.buf.buffered(), 2);
+
+        assert_eq!(r.recv(), Some(1));
+        assert_eq!(r.recv(), Some(2));
+        assert_eq!(r.buf.buffered(), 2);
+        assert_eq!(rr.recv(), Some(1));
+        assert_eq!(r.buf.buffered(), 1);
+        assert_eq!(rr.pending(), 1);
+
+        s.send(3);
+        drop(rr);
+        assert_eq!(r.buf.buffered(), 1);
+        assert_eq!(r.recv(), Some(3));
+        assert_eq!(r.buf.buffered(), 0);
+        assert!(r.is_alive());
+        drop(s);
+        assert!(!r.is_alive());
+    }
+
+    /// counts its clones.
+    struct Tracked(Arc<AtomicUsize>);
+    impl Clone for Tracked {
+        fn clone(&self) -> Self {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Self(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn unboxed() {
+        let clones = Arc::new(AtomicUsize::new(0));
+        let b = PipeStem::<Tracked>::new();
+        let s = b.require_inlet();
+        let r = b.require_outlet();
+        let rr = b.require_outlet();
+        drop(b);
+
+        // every outlet but the last to read an element gets a clone of it
+        s.send(Tracked(clones.clone()));
+        r.recv().unwrap();
+        assert_eq!(clones.load(Ordering::SeqCst), 1);
+        rr.recv().unwrap();
+        assert_eq!(clones.load(Ordering::SeqCst), 1);
+        assert_eq!(r.buf.buffered(), 0);
+    }
+
+    #[test]
+    fn capacity() {
+        let b = PipeStem::<u64>::with_config(PipeConfig {
+            capacity: Some(2),
+            usage: Arc::default(),
+        });
+        let s = b.require_inlet();
+        let r = b.require_outlet();
+        drop(b);
+
+        let counter = Arc::new(Counter(AtomicUsize::new(0)));
+        s.watch_room(&Waker::from(counter.clone()));
+
+        s.send(1);
+        assert!(s.has_room());
+        s.send(2);
+        assert!(!s.has_room());
+        r.recv();
+        assert!(s.has_room());
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+        r.recv();
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn usage() {
+        let usage = Arc::new(Usage::default());
+        let config = PipeConfig {
+            capacity: None,
+            usage: usage.clone(),
+        };
+        let b = PipeStem::<u64>::with_config(config.clone());
+        let s = b.require_inlet();
+        let r = b.require_outlet();
+        drop(b);
+        let unread = PipeStem::<u64>::with_config(config);
+        let ss = unread.require_inlet();
+        let rr = unread.require_outlet();
+        drop(unread);
+
+        s.send(1);
+        s.send(2);
+        ss.send(3);
+        assert_eq!((usage.buffered(), usage.produced()), (3, 3));
+        r.recv();
+        assert_eq!((usage.buffered(), usage.produced()), (2, 3));
+        drop((ss, rr));
+        assert_eq!((usage.buffered(), usage.produced()), (1, 3));
+        drop((s, r));
+        assert_eq!(usage.buffered(), 0);
+    }
+
     fn convert<'a, T: ?Sized, Q: ?Sized>(t: &'a T) -> &'a Q
     where
         &'a Q: From<&'a T>,
@@ -241,6 +574,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(unused_variables, clippy::useless_conversion)]
     fn dyntest() {
         let x = PhantomData::<String>;
 
@@ -0,0 +1,522 @@
+use std::{
+    cell::{Cell, RefCell},
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+};
+
+use crate::{
+    agent::{panicked, Agent, InputState, Step},
+    controller::{Execute, Slot, Stalled, Stats, StepLimitExceeded},
+    schedule::ReadyQueue,
+};
+
+type Task = Pin<Box<dyn Future<Output = anyhow::Result<()>>>>;
+type Job = Box<dyn FnOnce() + Send>;
+
+/// wakes a task of the runtime, possibly from another thread.
+struct TaskWaker {
+    id: usize,
+    /// read by [`Ready`].
+    woken: Arc<AtomicBool>,
+    queue: Arc<ReadyQueue>,
+    runtime: Thread,
+}
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::Release);
+        self.queue.push(self.id);
+        self.runtime.unpark();
+    }
+}
+
+/// a single helper thread running the steps of blocking agents.
+struct BlockingPool {
+    jobs: mpsc::Sender<Job>,
+    in_flight: Arc<AtomicUsize>,
+}
+impl BlockingPool {
+    fn new() -> Self {
+        let (jobs, rx) = mpsc::channel::<Job>();
+        thread::spawn(move || {
+            for job in rx {
+                job();
+            }
+        });
+        Self {
+            jobs,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// a panic of `f` resolves the job with an error.
+    fn run<T: Send + 'static>(&self, f: impl FnOnce() -> T + Send + 'static) -> Blocking<T> {
+        let state = Arc::new(Mutex::new(BlockingState {
+            result: None,
+            waker: None,
+        }));
+        let job_state = state.clone();
+        let in_flight = self.in_flight.clone();
+        in_flight.fetch_add(1, Ordering::AcqRel);
+        let job: Job = Box::new(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(f)).map_err(panicked);
+            let waker = {
+                let mut state = job_state.lock().unwrap();
+                state.result = Some(result);
+                state.waker.take()
+            };
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+            in_flight.fetch_sub(1, Ordering::AcqRel);
+        });
+        self.jobs.send(job).expect("blocking thread is gone");
+        Blocking { state }
+    }
+
+    fn is_busy(&self) -> bool {
+        self.in_flight.load(Ordering::Acquire) != 0
+    }
+}
+
+struct BlockingState<T> {
+    result: Option<anyhow::Result<T>>,
+    waker: Option<Waker>,
+}
+/// resolves once the job sent to [`BlockingPool`] returned.
+struct Blocking<T> {
+    state: Arc<Mutex<BlockingState<T>>>,
+}
+impl<T> Future for Blocking<T> {
+    type Output = anyhow::Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// pending once, and woken right away: lets the other tasks run.
+struct YieldNow(bool);
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// resolves once a pipe the agent watches woke its task, i.e. once stepping it again
+/// may get somewhere. the pipes hold the task waker, so polling needs no registration.
+/// it never resolves on its first poll, so that a poll takes at most one step.
+struct Ready {
+    woken: Arc<AtomicBool>,
+    polled: bool,
+}
+impl Future for Ready {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if !self.polled {
+            self.polled = true;
+            // woken during the step, maybe while it ran on the blocking thread
+            if self.woken.load(Ordering::Acquire) {
+                cx.waker().wake_by_ref();
+            }
+            return Poll::Pending;
+        }
+        if self.woken.swap(false, Ordering::AcqRel) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// stands in for an agent while its step runs on the [`BlockingPool`], so that its
+/// slot is still reported.
+#[derive(Debug)]
+struct InFlight;
+impl Agent for InFlight {
+    fn step(&self) -> anyhow::Result<Step> {
+        Ok(Step::Idle)
+    }
+    fn inputs(&self) -> Vec<InputState> {
+        Vec::new()
+    }
+    fn watch(&self, _waker: &Waker) {}
+}
+
+/// the future of an agent: steps it, then yields if it progressed or waits for its
+/// pipes if it idled.
+async fn drive(
+    cell: Rc<RefCell<Option<Slot>>>,
+    woken: Arc<AtomicBool>,
+    steps: Rc<Cell<usize>>,
+    pool: Rc<BlockingPool>,
+) -> anyhow::Result<()> {
+    loop {
+        // wakes from now on may concern this step's outcome
+        woken.store(false, Ordering::Release);
+        steps.set(steps.get() + 1);
+        let blocking = cell.borrow().as_ref().unwrap().agent.is_blocking();
+        let step = if blocking {
+            let agent = std::mem::replace(
+                &mut cell.borrow_mut().as_mut().unwrap().agent,
+                Box::new(InFlight),
+            );
+            // on a panic the agent is lost and [`InFlight`] stays in its slot
+            match pool.run(move || (agent.step(), agent)).await {
+                Ok((step, agent)) => {
+                    cell.borrow_mut().as_mut().unwrap().agent = agent;
+                    step
+                }
+                Err(e) => Err(e),
+            }
+        } else {
+            cell.borrow().as_ref().unwrap().agent.step()
+        };
+
+        let progressed = {
+            let mut guard = cell.borrow_mut();
+            let slot = guard.as_mut().unwrap();
+            match step {
+                Ok(Step::Progressed) => {
+                    slot.stats.progressed();
+                    true
+                }
+                Ok(Step::Idle) => {
+                    slot.stats.idled();
+                    false
+                }
+                Ok(Step::Finished) => return Ok(()),
+                Err(e) => return Err(slot.fail(e)),
+            }
+        };
+        if progressed {
+            YieldNow(false).await;
+        } else {
+            Ready {
+                woken: woken.clone(),
+                polled: false,
+            }
+            .await;
+        }
+    }
+}
+
+/// runs every agent as a future on a small single-threaded runtime.
+/// steps of blocking agents (e.g. printing) are sent to a helper thread,
+/// so they do not hold up the other agents. a task polled without stepping its
+/// agent takes no step.
+pub struct AsyncExecutor {
+    cells: Vec<Rc<RefCell<Option<Slot>>>>,
+    /// counted by the tasks.
+    steps: Rc<Cell<usize>>,
+    stats: Stats,
+}
+
+impl AsyncExecutor {
+    pub fn new(agents: Vec<Slot>) -> Self {
+        Self {
+            cells: agents
+                .into_iter()
+                .map(|slot| Rc::new(RefCell::new(Some(slot))))
+                .collect(),
+            steps: Rc::default(),
+            stats: Stats::default(),
+        }
+    }
+
+    fn live(&self) -> Vec<std::cell::Ref<'_, Option<Slot>>> {
+        self.cells
+            .iter()
+            .map(|cell| cell.borrow())
+            .filter(|slot| slot.is_some())
+            .collect()
+    }
+}
+
+impl Execute for AsyncExecutor {
+    fn run(&mut self, max_steps: Option<usize>) -> anyhow::Result<()> {
+        let queue = Arc::new(ReadyQueue::default());
+        let pool = Rc::new(BlockingPool::new());
+        let woken: Vec<Arc<AtomicBool>> = (0..self.cells.len())
+            .map(|_| Arc::new(AtomicBool::new(false)))
+            .collect();
+        let wakers: Vec<Waker> = (0..self.cells.len())
+            .map(|id| {
+                Waker::from(Arc::new(TaskWaker {
+                    id,
+                    woken: woken[id].clone(),
+                    queue: queue.clone(),
+                    runtime: thread::current(),
+                }))
+            })
+            .collect();
+        let mut tasks: Vec<Option<Task>> = Vec::new();
+        for (id, cell) in self.cells.iter().enumerate() {
+            if let Some(slot) = cell.borrow().as_ref() {
+                slot.agent.watch(&wakers[id]);
+                queue.push(id);
+            }
+            tasks.push(Some(Box::pin(drive(
+                cell.clone(),
+                woken[id].clone(),
+                self.steps.clone(),
+                pool.clone(),
+            ))));
+        }
+
+        let mut alive = tasks.len();
+        while alive > 0 {
+            let mut ids = queue.take();
+            if ids.is_empty() {
+                if pool.is_busy() {
+                    thread::park();
+                    continue;
+                }
+                // a job may have finished right before `is_busy`
+                ids = queue.take();
+                if ids.is_empty() {
+                    let live = self.live();
+                    return Err(Stalled::new(
+                        self.steps.get(),
+                        live.iter().flat_map(|s| s.as_ref()),
+                    )
+                    .into());
+                }
+            }
+            for id in ids {
+                let Some(task) = tasks[id].as_mut() else {
+                    continue;
+                };
+                // a poll takes at most one step
+                if max_steps.is_some_and(|max_steps| self.steps.get() >= max_steps) {
+                    let live = self.live();
+                    return Err(StepLimitExceeded::new(
+                        self.steps.get(),
+                        live.iter().flat_map(|s| s.as_ref()),
+                    )
+                    .into());
+                }
+                let mut cx = Context::from_waker(&wakers[id]);
+                if let Poll::Ready(result) = task.as_mut().poll(&mut cx) {
+                    tasks[id] = None;
+                    alive -= 1;
+                    result?;
+                    let slot = self.cells[id].borrow_mut().take().unwrap();
+                    self.stats
+                        .agents
+                        .push((slot.origin.clone(), slot.stats.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn stats(&self) -> Stats {
+        let mut stats = self.stats.clone();
+        stats.steps = self.steps.get();
+        for slot in self.live().iter().flat_map(|s| s.as_ref()) {
+            stats.agents.push((slot.origin.clone(), slot.stats.clone()));
+        }
+        stats.fired = stats.agents.iter().map(|(_, s)| s.fired).sum();
+        stats.idled = stats.agents.iter().map(|(_, s)| s.idled).sum();
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, task::Waker, thread, time::Duration};
+
+    use crate::{
+        agent::{Agent, InputState, Step},
+        controller::{Execute, Slot, Stalled, StepLimitExceeded},
+        pipe::{PipeBuilder, PipeReceiver, PipeSender},
+    };
+
+    use super::AsyncExecutor;
+
+    /// sleeps before each send, like an agent waiting on I/O.
+    #[derive(Debug)]
+    struct SlowProducer(RefCell<u64>, PipeSender<u64>);
+    impl Agent for SlowProducer {
+        fn step(&self) -> anyhow::Result<Step> {
+            let mut n = self.0.borrow_mut();
+            if *n == 0 {
+                return Ok(Step::Finished);
+            }
+            thread::sleep(Duration::from_millis(1));
+            self.1.send(*n);
+            *n -= 1;
+            Ok(Step::Progressed)
+        }
+        fn inputs(&self) -> Vec<InputState> {
+            Vec::new()
+        }
+        fn watch(&self, _waker: &Waker) {}
+        fn is_blocking(&self) -> bool {
+            true
+        }
+    }
+
+    /// sends once and panics on the blocking thread, with a receiver waiting on it.
+    #[derive(Debug)]
+    struct Panicker(PipeSender<u64>);
+    impl Agent for Panicker {
+        fn step(&self) -> anyhow::Result<Step> {
+            self.0.send(1);
+            panic!("boom");
+        }
+        fn inputs(&self) -> Vec<InputState> {
+            Vec::new()
+        }
+        fn watch(&self, _waker: &Waker) {}
+        fn is_blocking(&self) -> bool {
+            true
+        }
+    }
+
+    /// always has something to do.
+    #[derive(Debug)]
+    struct Spinner;
+    impl Agent for Spinner {
+        fn step(&self) -> anyhow::Result<Step> {
+            Ok(Step::Progressed)
+        }
+        fn inputs(&self) -> Vec<InputState> {
+            Vec::new()
+        }
+        fn watch(&self, _waker: &Waker) {}
+    }
+
+    #[derive(Debug)]
+    struct Summer(PipeReceiver<u64>, RefCell<u64>);
+    impl Agent for Summer {
+        fn step(&self) -> anyhow::Result<Step> {
+            if !self.0.is_alive() {
+                return Ok(Step::Finished);
+            }
+            match self.0.recv() {
+                Some(e) => {
+                    *self.1.borrow_mut() += *e;
+                    Ok(Step::Progressed)
+                }
+                None => Ok(Step::Idle),
+            }
+        }
+        fn inputs(&self) -> Vec<InputState> {
+            vec![InputState {
+                pipe: "A".to_string(),
+                pending: self.0.pending(),
+            }]
+        }
+        fn watch(&self, waker: &Waker) {
+            self.0.watch(waker);
+        }
+    }
+
+    #[test]
+    fn blocking_agent() -> anyhow::Result<()> {
+        let b = PipeBuilder::new::<u64>();
+        let agents = vec![
+            Slot::new(
+                "producer".to_string(),
+                Box::new(SlowProducer(RefCell::new(20), b.require_sender()?)),
+            ),
+            Slot::new(
+                "summer".to_string(),
+                Box::new(Summer(b.require_receiver()?, RefCell::new(0))),
+            ),
+        ];
+        drop(b);
+
+        let mut e = AsyncExecutor::new(agents);
+        e.run(None)?;
+        let stats = e.stats();
+        assert_eq!(stats.fired, 20 + 20);
+        assert_eq!(stats.agents.len(), 2);
+        // polls waiting on the blocking thread take no step; each agent finishes once
+        assert_eq!(stats.steps, stats.fired + stats.idled + 2);
+        Ok(())
+    }
+
+    #[test]
+    fn stalled() -> anyhow::Result<()> {
+        let b = PipeBuilder::new::<u64>();
+        let s = b.require_sender::<u64>()?;
+        let agents = vec![Slot::new(
+            "summer".to_string(),
+            Box::new(Summer(b.require_receiver()?, RefCell::new(0))),
+        )];
+        drop(b);
+
+        let mut e = AsyncExecutor::new(agents);
+        let err = e.run(None).unwrap_err().downcast::<Stalled>().unwrap();
+        assert_eq!(err.blocked[0].origin, "summer");
+        drop(s);
+        Ok(())
+    }
+
+    #[test]
+    fn in_flight() {
+        // the producer sleeps on the blocking thread while the spinner runs out of steps
+        let b = PipeBuilder::new::<u64>();
+        let producer = SlowProducer(RefCell::new(1), b.require_sender().unwrap());
+        let agents = vec![
+            Slot::new("producer".to_string(), Box::new(producer)),
+            Slot::new("spinner".to_string(), Box::new(Spinner)),
+        ];
+        drop(b);
+
+        let mut e = AsyncExecutor::new(agents);
+        let err = e.run(Some(10)).unwrap_err();
+        let err = err.downcast::<StepLimitExceeded>().unwrap();
+        assert_eq!(err.alive, ["producer", "spinner"]);
+        assert_eq!(e.stats().agents.len(), 2);
+    }
+
+    #[test]
+    fn blocking_panic() -> anyhow::Result<()> {
+        let b = PipeBuilder::new::<u64>();
+        let agents = vec![
+            Slot::new(
+                "summer".to_string(),
+                Box::new(Summer(b.require_receiver()?, RefCell::new(0))),
+            ),
+            Slot::new(
+                "panicker".to_string(),
+                Box::new(Panicker(b.require_sender()?)),
+            ),
+        ];
+        drop(b);
+
+        let mut e = AsyncExecutor::new(agents);
+        let err = e.run(None).unwrap_err();
+        assert_eq!(format!("{:#}", err), "panicker failed: panicked: boom");
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    task::{Wake, Waker},
+};
+
+/// agents waiting to be stepped, in FIFO order. an agent is queued at most once.
+#[derive(Debug, Default)]
+pub struct ReadyQueue {
+    inner: Mutex<ReadyInner>,
+}
+#[derive(Debug, Default)]
+struct ReadyInner {
+    queue: VecDeque<usize>,
+    queued: Vec<bool>,
+}
+
+impl ReadyQueue {
+    pub fn push(&self, id: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.queued.len() <= id {
+            inner.queued.resize(id + 1, false);
+        }
+        if !inner.queued[id] {
+            inner.queued[id] = true;
+            inner.queue.push_back(id);
+        }
+    }
+
+    /// takes every agent queued so far. agents woken afterwards are queued again.
+    pub fn take(&self) -> VecDeque<usize> {
+        let mut inner = self.inner.lock().unwrap();
+        let queue = std::mem::take(&mut inner.queue);
+        for id in queue.iter() {
+            inner.queued[*id] = false;
+        }
+        queue
+    }
+
+    /// a waker that queues agent `id` into this queue.
+    pub fn waker(self: &Arc<Self>, id: usize) -> Waker {
+        Waker::from(Arc::new(AgentWaker {
+            id,
+            queue: self.clone(),
+        }))
+    }
+}
+
+/// small deterministic PRNG (splitmix64), so that a schedule is reproducible from its seed.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// uniform in `0..n`. `n` must not be 0.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    pub fn shuffle<T>(&mut self, v: &mut [T]) {
+        for i in (1..v.len()).rev() {
+            v.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+struct AgentWaker {
+    id: usize,
+    queue: Arc<ReadyQueue>,
+}
+impl Wake for AgentWaker {
+    fn wake(self: Arc<Self>) {
+        self.queue.push(self.id);
+    }
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.queue.push(self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{ReadyQueue, Rng};
+
+    #[test]
+    fn dedup() {
+        let q = Arc::new(ReadyQueue::default());
+        let w = q.waker(3);
+        w.wake_by_ref();
+        q.push(1);
+        w.wake_by_ref();
+        assert_eq!(q.take(), [3, 1]);
+        assert!(q.take().is_empty());
+        w.wake();
+        assert_eq!(q.take(), [3]);
+    }
+
+    #[test]
+    fn shuffle_is_reproducible() {
+        let shuffled = |seed| {
+            let mut v: Vec<usize> = (0..16).collect();
+            Rng::new(seed).shuffle(&mut v);
+            v
+        };
+        assert_eq!(shuffled(7), shuffled(7));
+        assert_ne!(shuffled(7), shuffled(8));
+        let mut sorted = shuffled(7);
+        sorted.sort();
+        assert_eq!(sorted, (0..16).collect::<Vec<_>>());
+    }
+}
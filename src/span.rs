@@ -0,0 +1,138 @@
+use std::{fmt::Display, path::Path, sync::Arc};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// where an instruction sits in the script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    /// index of the instruction.
+    pub index: usize,
+    /// 1-based line of the first grapheme.
+    pub line: usize,
+    /// 1-based column of the first grapheme, counted in graphemes.
+    pub column: usize,
+    /// graphemes before the instruction.
+    pub offset: usize,
+    /// the graphemes of the instruction.
+    pub text: String,
+    /// the line the instruction starts on, without its line break.
+    pub source: Arc<str>,
+}
+
+pub fn is_line_break(g: &str) -> bool {
+    g == "\n" || g == "\r\n"
+}
+
+impl Span {
+    /// `message` followed by the source line of the instruction, with a caret under it.
+    pub fn render(&self, message: &str, script: &Path) -> String {
+        let gutter = " ".repeat(self.line.to_string().len());
+        // keep tabs so that the caret lines up with the source
+        let indent: String = self
+            .source
+            .graphemes(true)
+            .take(self.column - 1)
+            .map(|g| if g == "\t" { '\t' } else { ' ' })
+            .collect();
+        let width = self
+            .text
+            .graphemes(true)
+            .take_while(|g| !is_line_break(g))
+            .count()
+            .max(1);
+        format!(
+            "{message}\n{gutter}--> {}:{}:{}\n{gutter} |\n{} | {}\n{gutter} | {indent}{}",
+            script.display(),
+            self.line,
+            self.column,
+            self.line,
+            self.source,
+            "^".repeat(width),
+        )
+    }
+}
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "instruction {} {:?}", self.index, self.text)
+    }
+}
+
+/// what a precursor was made from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    /// the command line arguments fed to `#` and `@`.
+    Arguments,
+    Instruction(Span),
+}
+impl Origin {
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            Origin::Arguments => None,
+            Origin::Instruction(span) => Some(span),
+        }
+    }
+
+    /// attaches this origin to `e`, so that it can be rendered by [`render`].
+    pub fn locate(&self, e: anyhow::Error) -> anyhow::Error {
+        match self {
+            Origin::Arguments => e.context("command line arguments"),
+            Origin::Instruction(span) => e.context(span.clone()),
+        }
+    }
+}
+/// `{:#}` appends the line and column of an instruction.
+impl Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Origin::Arguments => f.write_str("command line arguments"),
+            Origin::Instruction(span) if f.alternate() => {
+                write!(f, "{} at {}:{}", span, span.line, span.column)
+            }
+            Origin::Instruction(span) => span.fmt(f),
+        }
+    }
+}
+
+/// renders `e` with the source of the instruction it was located at, if any.
+pub fn render(e: &anyhow::Error, script: &Path) -> Option<String> {
+    let span = e.downcast_ref::<Span>()?;
+    let message: Vec<String> = e
+        .chain()
+        .filter(|cause| cause.to_string() != span.to_string())
+        .map(|cause| cause.to_string())
+        .collect();
+    Some(span.render(&message.join(": "), script))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use anyhow::anyhow;
+
+    use crate::{
+        diagnostic::Diagnostics,
+        parser::{parse, Syntax},
+    };
+
+    use super::{render, Origin};
+
+    #[test]
+    fn locate() {
+        let source = "#0~!!HSA\n\tWSB!AaPX";
+        let instructions = parse(source, Syntax::Aligned, &mut Diagnostics::default()).unwrap();
+        let spans: Vec<_> = instructions.into_iter().map(|i| i.span).collect();
+        assert_eq!(spans.len(), 5);
+        assert_eq!((spans[1].line, spans[1].column), (1, 5));
+        // the line break is the first grapheme of instruction 2
+        assert_eq!((spans[2].line, spans[2].column), (1, 9));
+        assert_eq!((spans[3].line, spans[3].column), (2, 4));
+        assert_eq!(spans[3].offset, 12);
+
+        let e = Origin::Instruction(spans[3].clone()).locate(anyhow!("oops"));
+        assert_eq!(
+            render(&e, Path::new("a.ppop")).unwrap(),
+            "oops\n --> a.ppop:2:4\n  |\n2 | \tWSB!AaPX\n  | \t  ^^^^"
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use std::{
+    any::{type_name, TypeId},
+    fmt::{Debug, Display},
+    sync::LazyLock,
+};
+
+use crate::pipe::{Element, PipeBuilder, PipeConfig};
+
+/// a pipe element type known to ppop.
+pub struct PipeType {
+    pub id: TypeId,
+    pub name: &'static str,
+    /// the symbol of the type in the README, if it has one.
+    pub symbol: Option<&'static str>,
+    stem: fn(PipeConfig) -> PipeBuilder,
+}
+impl PipeType {
+    fn of<T: Element>(name: &'static str, symbol: Option<&'static str>) -> Self {
+        Self {
+            id: TypeId::of::<T>(),
+            name,
+            symbol,
+            stem: PipeBuilder::with_config::<T>,
+        }
+    }
+
+    /// a pipe carrying elements of this type.
+    pub fn stem(&self, config: PipeConfig) -> PipeBuilder {
+        (self.stem)(config)
+    }
+}
+impl Display for PipeType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name)
+    }
+}
+impl Debug for PipeType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.symbol {
+            Some(symbol) => write!(f, "{} ({})", self.name, symbol),
+            None => f.write_str(self.name),
+        }
+    }
+}
+
+static TYPES: LazyLock<Vec<PipeType>> = LazyLock::new(|| {
+    vec![
+        PipeType::of::<bool>("bool", Some("b")),
+        PipeType::of::<u8>("u8", Some("x")),
+        PipeType::of::<u64>("u64", Some("u")),
+        PipeType::of::<i64>("i64", Some("i")),
+        PipeType::of::<f64>("f64", Some("f")),
+        PipeType::of::<usize>("usize", Some("U")),
+        PipeType::of::<isize>("isize", Some("I")),
+        PipeType::of::<String>("String", Some("S")),
+        PipeType::of::<()>("()", None),
+    ]
+});
+
+/// every registered type, in the order of the README.
+pub fn all() -> &'static [PipeType] {
+    &TYPES
+}
+
+pub fn lookup(id: TypeId) -> Option<&'static PipeType> {
+    all().iter().find(|t| t.id == id)
+}
+
+/// the registered name of `T`, or its type name without module path for unregistered types.
+pub fn name_of<T: ?Sized + 'static>() -> &'static str {
+    if let Some(t) = lookup(TypeId::of::<T>()) {
+        return t.name;
+    }
+    let name = type_name::<T>();
+    match name.rsplit_once("::") {
+        Some((_, short)) if !name.contains('<') => short,
+        _ => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::TypeId;
+
+    use super::{all, lookup, name_of};
+
+    #[test]
+    fn registry() {
+        assert_eq!(name_of::<String>(), "String");
+        assert_eq!(name_of::<Vec<u8>>(), "alloc::vec::Vec<u8>");
+        assert_eq!(
+            format!("{:?}", lookup(TypeId::of::<usize>()).unwrap()),
+            "usize (U)"
+        );
+        let symbols: String = all().iter().flat_map(|t| t.symbol).collect();
+        assert_eq!(symbols, "bxuifUIS");
+    }
+}